@@ -2,6 +2,8 @@
 
 use sea_orm::entity::prelude::*;
 
+use super::sea_orm_active_enums::BanScope;
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "cidr_ban")]
 pub struct Model {
@@ -14,10 +16,17 @@ pub struct Model {
     pub reason: Option<String>,
     pub created_at: TimeDateTimeWithTimeZone,
     pub user_created_id: Option<i64>,
+    pub hit_count: i64,
+    pub last_hit_at: Option<TimeDateTimeWithTimeZone>,
+    pub expires_at: Option<TimeDateTimeWithTimeZone>,
+    pub source: Option<String>,
+    pub scope: BanScope,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::cidr_ban_history::Entity")]
+    CidrBanHistory,
     #[sea_orm(
         belongs_to = "super::user::Entity",
         from = "Column::UserCreatedId",
@@ -28,6 +37,12 @@ pub enum Relation {
     User,
 }
 
+impl Related<super::cidr_ban_history::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CidrBanHistory.def()
+    }
+}
+
 impl Related<super::user::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::User.def()
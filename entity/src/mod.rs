@@ -2,7 +2,12 @@
 
 pub mod prelude;
 
+pub mod cidr_allow;
 pub mod cidr_ban;
+pub mod cidr_ban_history;
+pub mod geo_ban;
+pub mod sea_orm_active_enums;
 pub mod url;
 pub mod url_filter;
+pub mod url_filter_history;
 pub mod user;
@@ -1,6 +1,10 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 1.0.0-rc.1
 
+pub use super::cidr_allow::Entity as CidrAllow;
 pub use super::cidr_ban::Entity as CidrBan;
+pub use super::cidr_ban_history::Entity as CidrBanHistory;
+pub use super::geo_ban::Entity as GeoBan;
 pub use super::url::Entity as Url;
 pub use super::url_filter::Entity as UrlFilter;
+pub use super::url_filter_history::Entity as UrlFilterHistory;
 pub use super::user::Entity as User;
@@ -10,7 +10,10 @@ pub struct Model {
     pub url: String,
     pub shady: String,
     pub created_at: TimeDateTimeWithTimeZone,
-    pub ip: Option<String>,
+    #[sea_orm(column_type = "Binary(16)", nullable)]
+    pub ip: Option<Vec<u8>>,
+    pub ip_hmac: Option<String>,
+    pub disabled: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -2,20 +2,28 @@
 
 use sea_orm::entity::prelude::*;
 
+use super::sea_orm_active_enums::FilterKind;
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "url_filter")]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i64,
-    #[sea_orm(unique)]
     pub filter: String,
+    pub kind: FilterKind,
     pub reason: Option<String>,
     pub created_at: TimeDateTimeWithTimeZone,
     pub user_created_id: Option<i64>,
+    pub hit_count: i64,
+    pub last_hit_at: Option<TimeDateTimeWithTimeZone>,
+    pub expires_at: Option<TimeDateTimeWithTimeZone>,
+    pub source: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::url_filter_history::Entity")]
+    UrlFilterHistory,
     #[sea_orm(
         belongs_to = "super::user::Entity",
         from = "Column::UserCreatedId",
@@ -26,6 +34,12 @@ pub enum Relation {
     User,
 }
 
+impl Related<super::url_filter_history::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UrlFilterHistory.def()
+    }
+}
+
 impl Related<super::user::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::User.def()
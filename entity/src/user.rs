@@ -14,10 +14,24 @@ pub struct Model {
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::cidr_allow::Entity")]
+    CidrAllow,
     #[sea_orm(has_many = "super::cidr_ban::Entity")]
     CidrBan,
+    #[sea_orm(has_many = "super::cidr_ban_history::Entity")]
+    CidrBanHistory,
+    #[sea_orm(has_many = "super::geo_ban::Entity")]
+    GeoBan,
     #[sea_orm(has_many = "super::url_filter::Entity")]
     UrlFilter,
+    #[sea_orm(has_many = "super::url_filter_history::Entity")]
+    UrlFilterHistory,
+}
+
+impl Related<super::cidr_allow::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CidrAllow.def()
+    }
 }
 
 impl Related<super::cidr_ban::Entity> for Entity {
@@ -26,10 +40,28 @@ impl Related<super::cidr_ban::Entity> for Entity {
     }
 }
 
+impl Related<super::cidr_ban_history::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CidrBanHistory.def()
+    }
+}
+
+impl Related<super::geo_ban::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::GeoBan.def()
+    }
+}
+
 impl Related<super::url_filter::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::UrlFilter.def()
     }
 }
 
+impl Related<super::url_filter_history::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UrlFilterHistory.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
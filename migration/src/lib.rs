@@ -20,6 +20,19 @@ mod m20240302_082409_create_user_table;
 mod m20240312_050549_create_url_filter_table;
 mod m20240312_184421_create_ip_filter_table;
 mod m20240314_011046_convert_to_timezone;
+mod m20240720_120000_add_url_filter_kind;
+mod m20240721_120000_add_hit_counters;
+mod m20240722_120000_add_cidr_ban_expiry;
+mod m20240723_120000_add_url_filter_expiry;
+mod m20240724_120000_add_url_disabled;
+mod m20240725_120000_add_import_source;
+mod m20240726_120000_create_cidr_allow_table;
+mod m20240727_120000_add_cidr_ban_scope;
+mod m20240728_120000_create_geo_ban_table;
+mod m20240729_120000_add_url_ip_hmac;
+mod m20240730_120000_convert_url_ip_to_binary;
+mod m20240731_120000_create_edit_history_tables;
+mod m20240801_120000_make_url_filter_unique_by_kind;
 
 pub struct Migrator;
 
@@ -33,6 +46,19 @@ impl MigratorTrait for Migrator {
             Box::new(m20240312_050549_create_url_filter_table::Migration),
             Box::new(m20240312_184421_create_ip_filter_table::Migration),
             Box::new(m20240314_011046_convert_to_timezone::Migration),
+            Box::new(m20240720_120000_add_url_filter_kind::Migration),
+            Box::new(m20240721_120000_add_hit_counters::Migration),
+            Box::new(m20240722_120000_add_cidr_ban_expiry::Migration),
+            Box::new(m20240723_120000_add_url_filter_expiry::Migration),
+            Box::new(m20240724_120000_add_url_disabled::Migration),
+            Box::new(m20240725_120000_add_import_source::Migration),
+            Box::new(m20240726_120000_create_cidr_allow_table::Migration),
+            Box::new(m20240727_120000_add_cidr_ban_scope::Migration),
+            Box::new(m20240728_120000_create_geo_ban_table::Migration),
+            Box::new(m20240729_120000_add_url_ip_hmac::Migration),
+            Box::new(m20240730_120000_convert_url_ip_to_binary::Migration),
+            Box::new(m20240731_120000_create_edit_history_tables::Migration),
+            Box::new(m20240801_120000_make_url_filter_unique_by_kind::Migration),
         ]
     }
 }
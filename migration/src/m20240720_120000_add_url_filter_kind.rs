@@ -0,0 +1,36 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Existing filters are all regexes
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UrlFilter::Table)
+                    .add_column(string_len(UrlFilter::Kind, 16).default("regex"))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UrlFilter::Table)
+                    .drop_column(UrlFilter::Kind)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum UrlFilter {
+    Table,
+    Kind,
+}
@@ -0,0 +1,64 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite can only add one column at a time
+        for table in [UrlFilter::Table.into_iden(), CidrBan::Table.into_iden()] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table.clone())
+                        .add_column(big_integer(Hits::HitCount).default(0))
+                        .to_owned(),
+                )
+                .await?;
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table)
+                        .add_column(timestamp_with_time_zone_null(Hits::LastHitAt))
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in [UrlFilter::Table.into_iden(), CidrBan::Table.into_iden()] {
+            for column in [Hits::HitCount, Hits::LastHitAt] {
+                manager
+                    .alter_table(
+                        Table::alter()
+                            .table(table.clone())
+                            .drop_column(column)
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum UrlFilter {
+    Table,
+}
+
+#[derive(DeriveIden)]
+enum CidrBan {
+    Table,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum Hits {
+    HitCount,
+    LastHitAt,
+}
@@ -0,0 +1,87 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Entries added by hand have no source; imported ones are replaced by source
+        for table in [UrlFilter::Table.into_iden(), CidrBan::Table.into_iden()] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table.clone())
+                        .add_column(string_null(Import::Source))
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-url_filter-source")
+                    .table(UrlFilter::Table)
+                    .col(Import::Source)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-cidr_ban-source")
+                    .table(CidrBan::Table)
+                    .col(Import::Source)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-url_filter-source")
+                    .table(UrlFilter::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-cidr_ban-source")
+                    .table(CidrBan::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        for table in [UrlFilter::Table.into_iden(), CidrBan::Table.into_iden()] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table)
+                        .drop_column(Import::Source)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum UrlFilter {
+    Table,
+}
+
+#[derive(DeriveIden)]
+enum CidrBan {
+    Table,
+}
+
+#[derive(DeriveIden)]
+enum Import {
+    Source,
+}
@@ -0,0 +1,90 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Exceptions to CIDR bans, stored the same way
+        manager
+            .create_table(
+                Table::create()
+                    .table(CidrAllow::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CidrAllow::Id)
+                            .big_integer()
+                            .primary_key()
+                            .not_null()
+                            .auto_increment(),
+                    )
+                    .col(binary_len(CidrAllow::RangeBegin, 16))
+                    .col(binary_len(CidrAllow::RangeEnd, 16))
+                    .col(string_null(CidrAllow::Reason))
+                    .col(
+                        ColumnDef::new(CidrAllow::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .col(big_integer_null(CidrAllow::UserCreatedId))
+                    .foreign_key(
+                        ForeignKeyCreateStatement::new()
+                            .name("fk-cidr_allow-user_created")
+                            .from(CidrAllow::Table, CidrAllow::UserCreatedId)
+                            .to(User::Table, User::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .check(Expr::col(CidrAllow::RangeBegin).lte(Expr::col(CidrAllow::RangeEnd)))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .table(CidrAllow::Table)
+                    .name("idx-cidr_allow-range_begin-range_end")
+                    .col(CidrAllow::RangeBegin)
+                    .col(CidrAllow::RangeEnd)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .table(CidrAllow::Table)
+                    .name("idx-cidr_allow-range_begin-range_end")
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(CidrAllow::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CidrAllow {
+    Table,
+    Id,
+    RangeBegin,
+    RangeEnd,
+    Reason,
+    CreatedAt,
+    UserCreatedId,
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
@@ -0,0 +1,36 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Existing bans only ever blocked submissions
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CidrBan::Table)
+                    .add_column(string_len(CidrBan::Scope, 16).default("submit"))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CidrBan::Table)
+                    .drop_column(CidrBan::Scope)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CidrBan {
+    Table,
+    Scope,
+}
@@ -0,0 +1,98 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Bans by where an IP is, according to the GeoIP databases
+        manager
+            .create_table(
+                Table::create()
+                    .table(GeoBan::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(GeoBan::Id)
+                            .big_integer()
+                            .primary_key()
+                            .not_null()
+                            .auto_increment(),
+                    )
+                    .col(string_len(GeoBan::Kind, 16))
+                    .col(string_len(GeoBan::Value, 64))
+                    .col(string_len(GeoBan::Scope, 16).default("submit"))
+                    .col(string_null(GeoBan::Reason))
+                    .col(
+                        ColumnDef::new(GeoBan::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .col(big_integer_null(GeoBan::UserCreatedId))
+                    .col(big_integer(GeoBan::HitCount).default(0))
+                    .col(timestamp_with_time_zone_null(GeoBan::LastHitAt))
+                    .col(timestamp_with_time_zone_null(GeoBan::ExpiresAt))
+                    .foreign_key(
+                        ForeignKeyCreateStatement::new()
+                            .name("fk-geo_ban-user_created")
+                            .from(GeoBan::Table, GeoBan::UserCreatedId)
+                            .to(User::Table, User::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .table(GeoBan::Table)
+                    .name("idx-geo_ban-kind-value-scope")
+                    .col(GeoBan::Kind)
+                    .col(GeoBan::Value)
+                    .col(GeoBan::Scope)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .table(GeoBan::Table)
+                    .name("idx-geo_ban-kind-value-scope")
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(GeoBan::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum GeoBan {
+    Table,
+    Id,
+    Kind,
+    Value,
+    Scope,
+    Reason,
+    CreatedAt,
+    UserCreatedId,
+    HitCount,
+    LastHitAt,
+    ExpiresAt,
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
@@ -0,0 +1,55 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // A keyed hash of the submitter's IP, so their links can be found without storing the IP
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Url::Table)
+                    .add_column(string_len_null(Url::IpHmac, 64))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-url-ip_hmac")
+                    .table(Url::Table)
+                    .col(Url::IpHmac)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-url-ip_hmac")
+                    .table(Url::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Url::Table)
+                    .drop_column(Url::IpHmac)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Url {
+    Table,
+    IpHmac,
+}
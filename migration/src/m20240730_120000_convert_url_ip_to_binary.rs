@@ -0,0 +1,136 @@
+use std::net::{IpAddr, Ipv6Addr};
+
+use sea_orm_migration::{
+    prelude::*,
+    schema::*,
+    sea_orm::{ConnectionTrait, DbBackend},
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// Copy every submitter IP from one column into another, converting it along the way.
+// Anything that can't be converted is left out.
+async fn convert_ips<V, F>(
+    manager: &SchemaManager<'_>,
+    from: Url,
+    to: Url,
+    convert: F,
+) -> Result<(), DbErr>
+where
+    V: sea_orm_migration::sea_orm::TryGetable,
+    F: Fn(V) -> Option<Value>,
+{
+    let db = manager.get_connection();
+    let backend: DbBackend = db.get_database_backend();
+
+    let select = Query::select()
+        .column(Url::Id)
+        .expr_as(Expr::col(from), Alias::new("ip"))
+        .from(Url::Table)
+        .and_where(Expr::col(from).is_not_null())
+        .to_owned();
+    for row in db.query_all(backend.build(&select)).await? {
+        let id: i64 = row.try_get("", "id")?;
+        let Some(ip) = convert(row.try_get("", "ip")?) else {
+            continue;
+        };
+        let update = Query::update()
+            .table(Url::Table)
+            .value(to, ip)
+            .and_where(Expr::col(Url::Id).eq(id))
+            .to_owned();
+        db.execute(backend.build(&update)).await?;
+    }
+
+    Ok(())
+}
+
+// Swap a column out for another, which takes its name
+async fn replace_column(manager: &SchemaManager<'_>, old: Url, new: Url) -> Result<(), DbErr> {
+    manager
+        .alter_table(Table::alter().table(Url::Table).drop_column(old).to_owned())
+        .await?;
+    manager
+        .alter_table(
+            Table::alter()
+                .table(Url::Table)
+                .rename_column(new, old)
+                .to_owned(),
+        )
+        .await
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Store IP's the same way as CIDR bans, so links can be found by network
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Url::Table)
+                    .add_column(binary_len_null(Url::IpBinary, 16))
+                    .to_owned(),
+            )
+            .await?;
+
+        convert_ips(manager, Url::Ip, Url::IpBinary, |ip: String| {
+            let octets = match ip.parse::<IpAddr>().ok()? {
+                IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+                IpAddr::V6(ip) => ip.octets(),
+            };
+            Some(octets.to_vec().into())
+        })
+        .await?;
+
+        replace_column(manager, Url::Ip, Url::IpBinary).await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-url-ip")
+                    .table(Url::Table)
+                    .col(Url::Ip)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-url-ip")
+                    .table(Url::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Url::Table)
+                    .add_column(string_null(Url::IpString))
+                    .to_owned(),
+            )
+            .await?;
+
+        convert_ips(manager, Url::Ip, Url::IpString, |ip: Vec<u8>| {
+            let octets = <[u8; 16]>::try_from(ip).ok()?;
+            Some(Ipv6Addr::from(octets).to_canonical().to_string().into())
+        })
+        .await?;
+
+        replace_column(manager, Url::Ip, Url::IpString).await
+    }
+}
+
+#[derive(Clone, Copy, DeriveIden)]
+enum Url {
+    Table,
+    Id,
+    Ip,
+    // Where IP's are converted to before taking the place of the old column
+    IpBinary,
+    IpString,
+}
@@ -0,0 +1,194 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // What CIDR bans were before each time they were edited
+        manager
+            .create_table(
+                Table::create()
+                    .table(CidrBanHistory::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CidrBanHistory::Id)
+                            .big_integer()
+                            .primary_key()
+                            .not_null()
+                            .auto_increment(),
+                    )
+                    .col(big_integer(CidrBanHistory::CidrBanId))
+                    .col(binary_len(CidrBanHistory::RangeBegin, 16))
+                    .col(binary_len(CidrBanHistory::RangeEnd, 16))
+                    .col(string_len(CidrBanHistory::Scope, 16))
+                    .col(string_null(CidrBanHistory::Reason))
+                    .col(timestamp_with_time_zone_null(CidrBanHistory::ExpiresAt))
+                    .col(
+                        ColumnDef::new(CidrBanHistory::ChangedAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .col(big_integer_null(CidrBanHistory::UserChangedId))
+                    .foreign_key(
+                        ForeignKeyCreateStatement::new()
+                            .name("fk-cidr_ban_history-cidr_ban")
+                            .from(CidrBanHistory::Table, CidrBanHistory::CidrBanId)
+                            .to(CidrBan::Table, CidrBan::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKeyCreateStatement::new()
+                            .name("fk-cidr_ban_history-user_changed")
+                            .from(CidrBanHistory::Table, CidrBanHistory::UserChangedId)
+                            .to(User::Table, User::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .table(CidrBanHistory::Table)
+                    .name("idx-cidr_ban_history-cidr_ban_id")
+                    .col(CidrBanHistory::CidrBanId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // What URL filters were before each time they were edited
+        manager
+            .create_table(
+                Table::create()
+                    .table(UrlFilterHistory::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UrlFilterHistory::Id)
+                            .big_integer()
+                            .primary_key()
+                            .not_null()
+                            .auto_increment(),
+                    )
+                    .col(big_integer(UrlFilterHistory::UrlFilterId))
+                    .col(string(UrlFilterHistory::Filter))
+                    .col(string_len(UrlFilterHistory::Kind, 16))
+                    .col(string_null(UrlFilterHistory::Reason))
+                    .col(timestamp_with_time_zone_null(UrlFilterHistory::ExpiresAt))
+                    .col(
+                        ColumnDef::new(UrlFilterHistory::ChangedAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .col(big_integer_null(UrlFilterHistory::UserChangedId))
+                    .foreign_key(
+                        ForeignKeyCreateStatement::new()
+                            .name("fk-url_filter_history-url_filter")
+                            .from(UrlFilterHistory::Table, UrlFilterHistory::UrlFilterId)
+                            .to(UrlFilter::Table, UrlFilter::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKeyCreateStatement::new()
+                            .name("fk-url_filter_history-user_changed")
+                            .from(UrlFilterHistory::Table, UrlFilterHistory::UserChangedId)
+                            .to(User::Table, User::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .table(UrlFilterHistory::Table)
+                    .name("idx-url_filter_history-url_filter_id")
+                    .col(UrlFilterHistory::UrlFilterId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .table(UrlFilterHistory::Table)
+                    .name("idx-url_filter_history-url_filter_id")
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(UrlFilterHistory::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .table(CidrBanHistory::Table)
+                    .name("idx-cidr_ban_history-cidr_ban_id")
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(CidrBanHistory::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CidrBanHistory {
+    Table,
+    Id,
+    CidrBanId,
+    RangeBegin,
+    RangeEnd,
+    Scope,
+    Reason,
+    ExpiresAt,
+    ChangedAt,
+    UserChangedId,
+}
+
+#[derive(DeriveIden)]
+enum UrlFilterHistory {
+    Table,
+    Id,
+    UrlFilterId,
+    Filter,
+    Kind,
+    Reason,
+    ExpiresAt,
+    ChangedAt,
+    UserChangedId,
+}
+
+#[derive(DeriveIden)]
+enum CidrBan {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum UrlFilter {
+    Table,
+    Id,
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
@@ -0,0 +1,207 @@
+use sea_orm::DbBackend;
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// Every column of url_filter, for copying it into a rebuilt table
+const COLUMNS: [UrlFilter; 10] = [
+    UrlFilter::Id,
+    UrlFilter::Filter,
+    UrlFilter::Kind,
+    UrlFilter::Reason,
+    UrlFilter::CreatedAt,
+    UrlFilter::UserCreatedId,
+    UrlFilter::HitCount,
+    UrlFilter::LastHitAt,
+    UrlFilter::ExpiresAt,
+    UrlFilter::Source,
+];
+
+fn source_index() -> IndexCreateStatement {
+    Index::create()
+        .name("idx-url_filter-source")
+        .table(UrlFilter::Table)
+        .col(UrlFilter::Source)
+        .to_owned()
+}
+
+fn filter_kind_index() -> IndexCreateStatement {
+    Index::create()
+        .name("idx-url_filter-filter-kind")
+        .table(UrlFilter::Table)
+        .col(UrlFilter::Filter)
+        .col(UrlFilter::Kind)
+        .unique()
+        .to_owned()
+}
+
+// SQLite can't drop a column's UNIQUE, so the table has to be rebuilt without it (or with it).
+// It's all done in one go, on one connection, with foreign keys off; otherwise dropping the old
+// table would take the filters' history with it.
+async fn rebuild_sqlite(manager: &SchemaManager<'_>, unique_filter: bool) -> Result<(), DbErr> {
+    let mut filter = string(UrlFilter::Filter);
+    if unique_filter {
+        filter.unique_key();
+    }
+
+    let create = Table::create()
+        .table(UrlFilter::NewTable)
+        .col(
+            ColumnDef::new(UrlFilter::Id)
+                .big_integer()
+                .primary_key()
+                .not_null()
+                .auto_increment(),
+        )
+        .col(filter)
+        .col(string(UrlFilter::Reason))
+        .col(
+            ColumnDef::new(UrlFilter::CreatedAt)
+                .timestamp_with_time_zone()
+                .default(Expr::current_timestamp())
+                .not_null(),
+        )
+        .col(big_integer(UrlFilter::UserCreatedId))
+        .col(string_len(UrlFilter::Kind, 16).default("regex"))
+        .col(big_integer(UrlFilter::HitCount).default(0))
+        .col(timestamp_with_time_zone_null(UrlFilter::LastHitAt))
+        .col(timestamp_with_time_zone_null(UrlFilter::ExpiresAt))
+        .col(string_null(UrlFilter::Source))
+        .foreign_key(
+            ForeignKeyCreateStatement::new()
+                .name("fk-url_filter-user_created")
+                .from(UrlFilter::NewTable, UrlFilter::UserCreatedId)
+                .to(User::Table, User::Id)
+                .on_update(ForeignKeyAction::Cascade)
+                .on_delete(ForeignKeyAction::SetNull),
+        )
+        .to_owned();
+    let copy = Query::insert()
+        .into_table(UrlFilter::NewTable)
+        .columns(COLUMNS)
+        .select_from(
+            Query::select()
+                .columns(COLUMNS)
+                .from(UrlFilter::Table)
+                .to_owned(),
+        )
+        .map_err(|e| DbErr::Migration(e.to_string()))?
+        .to_owned();
+    let drop = Table::drop().table(UrlFilter::Table).to_owned();
+    let rename = Table::rename()
+        .table(UrlFilter::NewTable, UrlFilter::Table)
+        .to_owned();
+
+    let mut statements = vec![
+        create.to_string(SqliteQueryBuilder),
+        copy.to_string(SqliteQueryBuilder),
+        drop.to_string(SqliteQueryBuilder),
+        rename.to_string(SqliteQueryBuilder),
+        source_index().to_string(SqliteQueryBuilder),
+    ];
+    if !unique_filter {
+        statements.push(filter_kind_index().to_string(SqliteQueryBuilder));
+    }
+
+    manager
+        .get_connection()
+        .execute_unprepared(&format!(
+            "PRAGMA foreign_keys = OFF; BEGIN; {}; COMMIT; PRAGMA foreign_keys = ON;",
+            statements.join("; ")
+        ))
+        .await
+        .map(|_| ())
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The same text can be a filter of more than one kind, like a domain and a substring
+        match manager.get_database_backend() {
+            DbBackend::Postgres => {
+                manager
+                    .get_connection()
+                    .execute_unprepared(
+                        r#"ALTER TABLE "url_filter" DROP CONSTRAINT "url_filter_filter_key""#,
+                    )
+                    .await?;
+            }
+            DbBackend::MySql => {
+                manager
+                    .drop_index(
+                        Index::drop()
+                            .name("filter")
+                            .table(UrlFilter::Table)
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+            DbBackend::Sqlite => return rebuild_sqlite(manager, false).await,
+        }
+
+        manager.create_index(filter_kind_index()).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() == DbBackend::Sqlite {
+            return rebuild_sqlite(manager, true).await;
+        }
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-url_filter-filter-kind")
+                    .table(UrlFilter::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        match manager.get_database_backend() {
+            DbBackend::Postgres => manager
+                .get_connection()
+                .execute_unprepared(
+                    r#"ALTER TABLE "url_filter" ADD CONSTRAINT "url_filter_filter_key" UNIQUE ("filter")"#,
+                )
+                .await
+                .map(|_| ()),
+            _ => {
+                manager
+                    .create_index(
+                        Index::create()
+                            .name("filter")
+                            .table(UrlFilter::Table)
+                            .col(UrlFilter::Filter)
+                            .unique()
+                            .to_owned(),
+                    )
+                    .await
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, DeriveIden)]
+enum UrlFilter {
+    Table,
+    // Where url_filter is rebuilt on SQLite before taking its place
+    #[sea_orm(iden = "url_filter_new")]
+    NewTable,
+    Id,
+    Filter,
+    Kind,
+    Reason,
+    CreatedAt,
+    UserCreatedId,
+    HitCount,
+    LastHitAt,
+    ExpiresAt,
+    Source,
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Iden)]
+enum User {
+    Table,
+    Id,
+}
@@ -14,46 +14,401 @@
 
 // Database mutation operations for ShadyURL
 
+use std::{collections::HashSet, net::IpAddr};
+
 use ipnetwork::{IpNetwork, Ipv6Network};
-use sea_orm::*;
+use sea_orm::{
+    prelude::{Expr, TimeDateTimeWithTimeZone},
+    *,
+};
 
-use ::entity::{cidr_ban, prelude::*, url, url_filter, user};
+use ::entity::{
+    cidr_allow, cidr_ban, cidr_ban_history, geo_ban,
+    prelude::*,
+    sea_orm_active_enums::{BanScope, FilterKind, GeoKind},
+    url, url_filter, url_filter_history, user,
+};
 
 use crate::Query;
 
+// How many rows are inserted or matched by ID at once, to stay under parameter limits
+const CHUNK: usize = 500;
+
+// What happened when replacing the entries from an import source
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ImportResult {
+    pub removed: u64,
+    pub added: u64,
+    // Entries that already existed, whether added by hand or from another source
+    pub skipped: u64,
+}
+
+// Whether two reasons are the same, treating a missing reason as an empty one
+fn same_reason(a: Option<&str>, b: Option<&str>) -> bool {
+    a.unwrap_or_default().trim() == b.unwrap_or_default().trim()
+}
+
+// Get the range of a network, as stored in the database
+pub(crate) fn network_range(network: IpNetwork) -> (Vec<u8>, Vec<u8>) {
+    let (start, end) = match network {
+        IpNetwork::V4(n) => {
+            let addr = n.network().to_ipv6_mapped();
+            let prefix = n.prefix() + 96;
+            let network =
+                // Should not fail, as this is coming from a valid IPv4 network
+                Ipv6Network::new(addr, prefix).expect("Could not create IPv6 network");
+            (network.network(), network.broadcast())
+        }
+        IpNetwork::V6(n) => (n.network(), n.broadcast()),
+    };
+    (start.octets().to_vec(), end.octets().to_vec())
+}
+
+// An address as stored, the same way as the ends of a range
+pub(crate) fn ip_octets(addr: IpAddr) -> Vec<u8> {
+    match addr {
+        IpAddr::V4(addr) => addr.to_ipv6_mapped().octets(),
+        IpAddr::V6(addr) => addr.octets(),
+    }
+    .to_vec()
+}
+
 pub struct Mutation;
 
 impl Mutation {
-    // Create a CIDR ban given a network, reason, and user
+    // Create a CIDR allow given a network, reason, and user
+    pub async fn create_cidr_allow(
+        db: &DbConn,
+        network: IpNetwork,
+        reason: Option<String>,
+        user: &user::Model,
+    ) -> Result<cidr_allow::ActiveModel, DbErr> {
+        let (start, end) = network_range(network);
+
+        cidr_allow::ActiveModel {
+            range_begin: Set(start),
+            range_end: Set(end),
+            reason: Set(reason),
+            user_created_id: Set(Some(user.id)),
+            ..Default::default()
+        }
+        .save(db)
+        .await
+    }
+
+    // Create a CIDR ban given a network, scope, reason, user, and optional expiry time
     pub async fn create_cidr_ban(
         db: &DbConn,
         network: IpNetwork,
+        scope: BanScope,
         reason: Option<String>,
         user: &user::Model,
+        expires_at: Option<TimeDateTimeWithTimeZone>,
     ) -> Result<cidr_ban::ActiveModel, DbErr> {
-        let (start, end) = match network {
-            IpNetwork::V4(n) => {
-                let addr = n.network().to_ipv6_mapped();
-                let prefix = n.prefix() + 96;
-                let network =
-                    // Should not fail, as this is coming from a valid IPv4 network
-                    Ipv6Network::new(addr, prefix).expect("Could not create IPv6 network");
-                (network.network(), network.broadcast())
-            }
-            IpNetwork::V6(n) => (n.network(), n.broadcast()),
-        };
+        let (start, end) = network_range(network);
 
         cidr_ban::ActiveModel {
-            range_begin: ActiveValue::Set(start.octets().to_vec()),
-            range_end: ActiveValue::Set(end.octets().to_vec()),
+            range_begin: ActiveValue::Set(start),
+            range_end: ActiveValue::Set(end),
+            scope: Set(scope),
             reason: ActiveValue::Set(reason),
             user_created_id: Set(Some(user.id)),
+            expires_at: Set(expires_at),
             ..Default::default()
         }
         .save(db)
         .await
     }
 
+    // Create a GeoIP ban given a kind, value, scope, reason, user, and optional expiry time
+    pub async fn create_geo_ban(
+        db: &DbConn,
+        kind: GeoKind,
+        value: String,
+        scope: BanScope,
+        reason: Option<String>,
+        user: &user::Model,
+        expires_at: Option<TimeDateTimeWithTimeZone>,
+    ) -> Result<geo_ban::ActiveModel, DbErr> {
+        geo_ban::ActiveModel {
+            kind: Set(kind),
+            value: Set(value),
+            scope: Set(scope),
+            reason: Set(reason),
+            user_created_id: Set(Some(user.id)),
+            expires_at: Set(expires_at),
+            ..Default::default()
+        }
+        .save(db)
+        .await
+    }
+
+    // Replace CIDR bans with bans on the given networks, sharing a scope, reason, creator, and
+    // expiry. This is done in one transaction, so the covered range is never left unbanned.
+    pub async fn merge_cidr_bans(
+        db: &DbConn,
+        remove: &[i64],
+        networks: &[IpNetwork],
+        scope: BanScope,
+        reason: Option<String>,
+        user_id: Option<i64>,
+        expires_at: Option<TimeDateTimeWithTimeZone>,
+    ) -> Result<(), DbErr> {
+        let txn = db.begin().await?;
+        CidrBan::delete_many()
+            .filter(cidr_ban::Column::Id.is_in(remove.iter().copied()))
+            .exec(&txn)
+            .await?;
+
+        if !networks.is_empty() {
+            CidrBan::insert_many(networks.iter().map(|&network| {
+                let (start, end) = network_range(network);
+                cidr_ban::ActiveModel {
+                    range_begin: Set(start),
+                    range_end: Set(end),
+                    scope: Set(scope),
+                    reason: Set(reason.clone()),
+                    user_created_id: Set(user_id),
+                    expires_at: Set(expires_at),
+                    ..Default::default()
+                }
+            }))
+            .exec(&txn)
+            .await?;
+        }
+
+        txn.commit().await?;
+        Ok(())
+    }
+
+    // Change a CIDR ban's network (None to keep its range), scope, reason, and expiry, keeping
+    // what it was before in its history along with who changed it. Edited bans no longer belong
+    // to the source they were imported from, so importing it again won't undo the change.
+    // Returns None if there's no such ban.
+    pub async fn update_cidr_ban(
+        db: &DbConn,
+        id: i64,
+        network: Option<IpNetwork>,
+        scope: BanScope,
+        reason: Option<String>,
+        expires_at: Option<TimeDateTimeWithTimeZone>,
+        user: &user::Model,
+    ) -> Result<Option<cidr_ban::Model>, DbErr> {
+        let txn = db.begin().await?;
+        let Some(ban) = CidrBan::find_by_id(id).one(&txn).await? else {
+            return Ok(None);
+        };
+
+        let (start, end) = match network {
+            Some(network) => network_range(network),
+            None => (ban.range_begin.clone(), ban.range_end.clone()),
+        };
+        if ban.range_begin == start
+            && ban.range_end == end
+            && ban.scope == scope
+            && same_reason(ban.reason.as_deref(), reason.as_deref())
+            && ban.expires_at == expires_at
+        {
+            // Nothing to record
+            return Ok(Some(ban));
+        }
+
+        cidr_ban_history::ActiveModel {
+            cidr_ban_id: Set(ban.id),
+            range_begin: Set(ban.range_begin.clone()),
+            range_end: Set(ban.range_end.clone()),
+            scope: Set(ban.scope),
+            reason: Set(ban.reason.clone()),
+            expires_at: Set(ban.expires_at),
+            user_changed_id: Set(Some(user.id)),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        let mut ban: cidr_ban::ActiveModel = ban.into();
+        ban.range_begin = Set(start);
+        ban.range_end = Set(end);
+        ban.scope = Set(scope);
+        ban.reason = Set(reason);
+        ban.expires_at = Set(expires_at);
+        ban.source = Set(None);
+        let ban = ban.update(&txn).await?;
+
+        txn.commit().await?;
+        Ok(Some(ban))
+    }
+
+    // Change a URL filter's filter, kind, reason, and expiry, keeping what it was before in its
+    // history along with who changed it. As with CIDR bans, it no longer belongs to its source.
+    // Returns None if there's no such filter.
+    pub async fn update_url_filter(
+        db: &DbConn,
+        id: i64,
+        filter: String,
+        kind: FilterKind,
+        reason: Option<String>,
+        expires_at: Option<TimeDateTimeWithTimeZone>,
+        user: &user::Model,
+    ) -> Result<Option<url_filter::Model>, DbErr> {
+        let txn = db.begin().await?;
+        let Some(url_filter) = UrlFilter::find_by_id(id).one(&txn).await? else {
+            return Ok(None);
+        };
+
+        if url_filter.filter == filter
+            && url_filter.kind == kind
+            && same_reason(url_filter.reason.as_deref(), reason.as_deref())
+            && url_filter.expires_at == expires_at
+        {
+            // Nothing to record
+            return Ok(Some(url_filter));
+        }
+
+        url_filter_history::ActiveModel {
+            url_filter_id: Set(url_filter.id),
+            filter: Set(url_filter.filter.clone()),
+            kind: Set(url_filter.kind),
+            reason: Set(url_filter.reason.clone()),
+            expires_at: Set(url_filter.expires_at),
+            user_changed_id: Set(Some(user.id)),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        let mut url_filter: url_filter::ActiveModel = url_filter.into();
+        url_filter.filter = Set(filter);
+        url_filter.kind = Set(kind);
+        url_filter.reason = Set(reason);
+        url_filter.expires_at = Set(expires_at);
+        url_filter.source = Set(None);
+        let url_filter = url_filter.update(&txn).await?;
+
+        txn.commit().await?;
+        Ok(Some(url_filter))
+    }
+
+    // Replace every CIDR ban imported from a source with the given networks and reasons.
+    // This is done in one transaction, so a failed import leaves the old set in place.
+    pub async fn replace_cidr_bans(
+        db: &DbConn,
+        source: &str,
+        scope: BanScope,
+        bans: Vec<(IpNetwork, String)>,
+        user: &user::Model,
+    ) -> Result<ImportResult, DbErr> {
+        let txn = db.begin().await?;
+        let mut result = ImportResult {
+            removed: CidrBan::delete_many()
+                .filter(cidr_ban::Column::Source.eq(source))
+                .exec(&txn)
+                .await?
+                .rows_affected,
+            ..Default::default()
+        };
+
+        // Both ends of a range are unique
+        let existing: Vec<(Vec<u8>, Vec<u8>)> = CidrBan::find()
+            .select_only()
+            .column(cidr_ban::Column::RangeBegin)
+            .column(cidr_ban::Column::RangeEnd)
+            .into_tuple()
+            .all(&txn)
+            .await?;
+        let (mut begins, mut ends): (HashSet<_>, HashSet<_>) = existing.into_iter().unzip();
+
+        let mut new_bans = Vec::new();
+        for (network, reason) in bans {
+            let (start, end) = network_range(network);
+            if begins.contains(&start) || ends.contains(&end) {
+                result.skipped += 1;
+                continue;
+            }
+
+            begins.insert(start.clone());
+            ends.insert(end.clone());
+            new_bans.push(cidr_ban::ActiveModel {
+                range_begin: Set(start),
+                range_end: Set(end),
+                scope: Set(scope),
+                reason: Set(Some(reason)),
+                user_created_id: Set(Some(user.id)),
+                source: Set(Some(source.to_string())),
+                ..Default::default()
+            });
+        }
+
+        result.added = new_bans.len() as u64;
+        for chunk in new_bans.chunks(CHUNK) {
+            CidrBan::insert_many(chunk.iter().cloned())
+                .exec(&txn)
+                .await?;
+        }
+
+        txn.commit().await?;
+        Ok(result)
+    }
+
+    // Replace every URL filter imported from a source with the given filters and reasons.
+    // This is done in one transaction, so a failed import leaves the old set in place.
+    pub async fn replace_url_filters(
+        db: &DbConn,
+        source: &str,
+        kind: FilterKind,
+        filters: Vec<(String, String)>,
+        user: &user::Model,
+    ) -> Result<ImportResult, DbErr> {
+        let txn = db.begin().await?;
+        let mut result = ImportResult {
+            removed: UrlFilter::delete_many()
+                .filter(url_filter::Column::Source.eq(source))
+                .exec(&txn)
+                .await?
+                .rows_affected,
+            ..Default::default()
+        };
+
+        // Filters only clash with ones of the same kind
+        let mut existing: HashSet<String> = UrlFilter::find()
+            .select_only()
+            .column(url_filter::Column::Filter)
+            .filter(url_filter::Column::Kind.eq(kind))
+            .into_tuple()
+            .all(&txn)
+            .await?
+            .into_iter()
+            .collect();
+
+        let mut new_filters = Vec::new();
+        for (filter, reason) in filters {
+            if existing.contains(&filter) {
+                result.skipped += 1;
+                continue;
+            }
+
+            existing.insert(filter.clone());
+            new_filters.push(url_filter::ActiveModel {
+                filter: Set(filter),
+                kind: Set(kind),
+                reason: Set(Some(reason)),
+                user_created_id: Set(Some(user.id)),
+                source: Set(Some(source.to_string())),
+                ..Default::default()
+            });
+        }
+
+        result.added = new_filters.len() as u64;
+        for chunk in new_filters.chunks(CHUNK) {
+            UrlFilter::insert_many(chunk.iter().cloned())
+                .exec(&txn)
+                .await?;
+        }
+
+        txn.commit().await?;
+        Ok(result)
+    }
+
     // Create a user given a username and password hash
     pub async fn create_user(
         db: &DbConn,
@@ -69,38 +424,56 @@ impl Mutation {
         .await
     }
 
-    // Create a URL given a url, shady "filename", and IP
+    // Create a URL given a url, shady "filename", and whatever is kept of the submitter's IP
     pub async fn create_url(
         db: &DbConn,
         url: &str,
         shady: &str,
-        ip: Option<String>,
+        ip: Option<IpAddr>,
+        ip_hmac: Option<String>,
     ) -> Result<url::ActiveModel, DbErr> {
         url::ActiveModel {
             url: Set(url.to_owned()),
             shady: Set(shady.to_owned()),
-            ip: Set(ip),
+            ip: Set(ip.map(ip_octets)),
+            ip_hmac: Set(ip_hmac),
             ..Default::default()
         }
         .save(db)
         .await
     }
 
-    // Create a URL filter given a filter string, an optional reason, and a user.
+    // Create a URL filter given a filter string, its kind, an optional reason, and a user.
+    // An expired copy of it that hasn't been cleaned up yet is deleted to make way for it.
     pub async fn create_url_filter(
         db: &DbConn,
         filter: String,
+        kind: FilterKind,
         reason: Option<String>,
         user: &user::Model,
+        expires_at: Option<TimeDateTimeWithTimeZone>,
     ) -> Result<url_filter::ActiveModel, DbErr> {
-        url_filter::ActiveModel {
+        let txn = db.begin().await?;
+        UrlFilter::delete_many()
+            .filter(url_filter::Column::Filter.eq(&filter))
+            .filter(url_filter::Column::Kind.eq(kind))
+            .filter(url_filter::Column::ExpiresAt.lte(TimeDateTimeWithTimeZone::now_utc()))
+            .exec(&txn)
+            .await?;
+
+        let url_filter = url_filter::ActiveModel {
             filter: Set(filter),
+            kind: Set(kind),
             reason: Set(reason),
             user_created_id: Set(Some(user.id)),
+            expires_at: Set(expires_at),
             ..Default::default()
         }
-        .save(db)
-        .await
+        .save(&txn)
+        .await?;
+
+        txn.commit().await?;
+        Ok(url_filter)
     }
 
     // Change a user password given a username and password hash.
@@ -118,11 +491,108 @@ impl Mutation {
         user.update(db).await.map(Into::into)
     }
 
+    // Add hits to a CIDR ban, and set when it was last hit.
+    pub async fn add_cidr_ban_hits(
+        db: &DbConn,
+        id: i64,
+        count: i64,
+        last_hit_at: TimeDateTimeWithTimeZone,
+    ) -> Result<UpdateResult, DbErr> {
+        CidrBan::update_many()
+            .col_expr(
+                cidr_ban::Column::HitCount,
+                Expr::col(cidr_ban::Column::HitCount).add(count),
+            )
+            .col_expr(cidr_ban::Column::LastHitAt, Expr::value(last_hit_at))
+            .filter(cidr_ban::Column::Id.eq(id))
+            .exec(db)
+            .await
+    }
+
+    // Add hits to a GeoIP ban, and set when it was last hit.
+    pub async fn add_geo_ban_hits(
+        db: &DbConn,
+        id: i64,
+        count: i64,
+        last_hit_at: TimeDateTimeWithTimeZone,
+    ) -> Result<UpdateResult, DbErr> {
+        GeoBan::update_many()
+            .col_expr(
+                geo_ban::Column::HitCount,
+                Expr::col(geo_ban::Column::HitCount).add(count),
+            )
+            .col_expr(geo_ban::Column::LastHitAt, Expr::value(last_hit_at))
+            .filter(geo_ban::Column::Id.eq(id))
+            .exec(db)
+            .await
+    }
+
+    // Add hits to a URL filter, and set when it was last hit.
+    pub async fn add_url_filter_hits(
+        db: &DbConn,
+        id: i64,
+        count: i64,
+        last_hit_at: TimeDateTimeWithTimeZone,
+    ) -> Result<UpdateResult, DbErr> {
+        UrlFilter::update_many()
+            .col_expr(
+                url_filter::Column::HitCount,
+                Expr::col(url_filter::Column::HitCount).add(count),
+            )
+            .col_expr(url_filter::Column::LastHitAt, Expr::value(last_hit_at))
+            .filter(url_filter::Column::Id.eq(id))
+            .exec(db)
+            .await
+    }
+
+    // Delete a CIDR allow by ID.
+    pub async fn delete_cidr_allow(db: &DbConn, id: i64) -> Result<DeleteResult, DbErr> {
+        CidrAllow::delete_by_id(id).exec(db).await
+    }
+
     // Delete a CIDR ban by ID.
     pub async fn delete_cidr_ban(db: &DbConn, id: i64) -> Result<DeleteResult, DbErr> {
         CidrBan::delete_by_id(id).exec(db).await
     }
 
+    // Delete a GeoIP ban by ID.
+    pub async fn delete_geo_ban(db: &DbConn, id: i64) -> Result<DeleteResult, DbErr> {
+        GeoBan::delete_by_id(id).exec(db).await
+    }
+
+    // Delete CIDR bans that expired before the given time.
+    pub async fn delete_expired_cidr_bans(
+        db: &DbConn,
+        now: TimeDateTimeWithTimeZone,
+    ) -> Result<DeleteResult, DbErr> {
+        CidrBan::delete_many()
+            .filter(cidr_ban::Column::ExpiresAt.lte(now))
+            .exec(db)
+            .await
+    }
+
+    // Delete GeoIP bans that expired before the given time.
+    pub async fn delete_expired_geo_bans(
+        db: &DbConn,
+        now: TimeDateTimeWithTimeZone,
+    ) -> Result<DeleteResult, DbErr> {
+        GeoBan::delete_many()
+            .filter(geo_ban::Column::ExpiresAt.lte(now))
+            .exec(db)
+            .await
+    }
+
+    // Delete URL filters that expired before the given time.
+    pub async fn delete_expired_url_filters(
+        db: &DbConn,
+        now: TimeDateTimeWithTimeZone,
+    ) -> Result<DeleteResult, DbErr> {
+        UrlFilter::delete_many()
+            .filter(url_filter::Column::ExpiresAt.lte(now))
+            .exec(db)
+            .await
+    }
+
     // Delete a user by ID.
     pub async fn delete_user(db: &DbConn, id: i64) -> Result<DeleteResult, DbErr> {
         User::delete_by_id(id).exec(db).await
@@ -133,6 +603,59 @@ impl Mutation {
         Url::delete_by_id(id).exec(db).await
     }
 
+    // Delete several URL's by ID.
+    // This is done a chunk at a time, so there's no limit on how many there are.
+    pub async fn delete_urls(db: &DbConn, ids: &[i64]) -> Result<DeleteResult, DbErr> {
+        let txn = db.begin().await?;
+        let mut rows_affected = 0;
+        for chunk in ids.chunks(CHUNK) {
+            rows_affected += Url::delete_many()
+                .filter(url::Column::Id.is_in(chunk.iter().copied()))
+                .exec(&txn)
+                .await?
+                .rows_affected;
+        }
+
+        txn.commit().await?;
+        Ok(DeleteResult { rows_affected })
+    }
+
+    // Disable several URL's by ID, so they no longer redirect.
+    // This is done a chunk at a time, so there's no limit on how many there are.
+    pub async fn disable_urls(db: &DbConn, ids: &[i64]) -> Result<UpdateResult, DbErr> {
+        let txn = db.begin().await?;
+        let mut rows_affected = 0;
+        for chunk in ids.chunks(CHUNK) {
+            rows_affected += Url::update_many()
+                .col_expr(url::Column::Disabled, Expr::value(true))
+                .filter(url::Column::Id.is_in(chunk.iter().copied()))
+                .exec(&txn)
+                .await?
+                .rows_affected;
+        }
+
+        txn.commit().await?;
+        Ok(UpdateResult { rows_affected })
+    }
+
+    // Forget the submitter IP's of URL's created before a given time.
+    pub async fn forget_url_ips(
+        db: &DbConn,
+        before: TimeDateTimeWithTimeZone,
+    ) -> Result<UpdateResult, DbErr> {
+        Url::update_many()
+            .col_expr(url::Column::Ip, Expr::value(Option::<Vec<u8>>::None))
+            .col_expr(url::Column::IpHmac, Expr::value(Option::<String>::None))
+            .filter(url::Column::CreatedAt.lt(before))
+            .filter(
+                Condition::any()
+                    .add(url::Column::Ip.is_not_null())
+                    .add(url::Column::IpHmac.is_not_null()),
+            )
+            .exec(db)
+            .await
+    }
+
     // Delete a URL filter by ID.
     pub async fn delete_url_filter(db: &DbConn, id: i64) -> Result<DeleteResult, DbErr> {
         UrlFilter::delete_by_id(id).exec(db).await
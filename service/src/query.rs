@@ -16,9 +16,24 @@
 
 use std::net::IpAddr;
 
-use sea_orm::*;
+use ipnetwork::IpNetwork;
+use sea_orm::{prelude::TimeDateTimeWithTimeZone, *};
 
-use ::entity::{cidr_ban, prelude::*, url, url_filter, user};
+use ::entity::{
+    cidr_allow, cidr_ban, cidr_ban_history, geo_ban, prelude::*, sea_orm_active_enums::BanScope,
+    url, url_filter, url_filter_history, user,
+};
+
+use crate::mutation::{ip_octets, network_range};
+
+// A ban's ID, scope, and expiry, along with its range
+pub type BanRange = (
+    i64,
+    BanScope,
+    Option<TimeDateTimeWithTimeZone>,
+    Vec<u8>,
+    Vec<u8>,
+);
 
 pub struct Query;
 
@@ -28,6 +43,29 @@ impl Query {
         CidrBan::find_by_id(id).one(db).await
     }
 
+    // Find the CIDR ban on exactly a network, if there is one.
+    pub async fn find_cidr_ban_by_network(
+        db: &DbConn,
+        network: IpNetwork,
+    ) -> Result<Option<cidr_ban::Model>, DbErr> {
+        let (begin, end) = network_range(network);
+        CidrBan::find()
+            .filter(cidr_ban::Column::RangeBegin.eq(begin))
+            .filter(cidr_ban::Column::RangeEnd.eq(end))
+            .one(db)
+            .await
+    }
+
+    // Find a CIDR allow by ID.
+    pub async fn find_cidr_allow(db: &DbConn, id: i64) -> Result<Option<cidr_allow::Model>, DbErr> {
+        CidrAllow::find_by_id(id).one(db).await
+    }
+
+    // Find a GeoIP ban by ID.
+    pub async fn find_geo_ban(db: &DbConn, id: i64) -> Result<Option<geo_ban::Model>, DbErr> {
+        GeoBan::find_by_id(id).one(db).await
+    }
+
     // Find a user by ID.
     pub async fn find_user_by_id(db: &DbConn, id: i64) -> Result<Option<user::Model>, DbErr> {
         User::find_by_id(id).one(db).await
@@ -71,6 +109,60 @@ impl Query {
         Url::find().order_by_asc(url::Column::Id).all(db).await
     }
 
+    // Get up to `limit` URL's with ID's after `after`, in order, for going through every URL a
+    // batch at a time.
+    pub async fn fetch_urls_after(
+        db: &DbConn,
+        after: i64,
+        limit: u64,
+    ) -> Result<Vec<url::Model>, DbErr> {
+        Url::find()
+            .filter(url::Column::Id.gt(after))
+            .order_by_asc(url::Column::Id)
+            .limit(limit)
+            .all(db)
+            .await
+    }
+
+    // Get all URL's stored under any of the given IP's, or the given keyed hash of an IP.
+    pub async fn find_urls_by_submitter(
+        db: &DbConn,
+        ips: &[IpAddr],
+        ip_hmac: Option<String>,
+    ) -> Result<Vec<url::Model>, DbErr> {
+        Url::find()
+            .filter(
+                Condition::any()
+                    .add(url::Column::Ip.is_in(ips.iter().copied().map(ip_octets)))
+                    .add_option(ip_hmac.map(|ip_hmac| url::Column::IpHmac.eq(ip_hmac))),
+            )
+            .order_by_asc(url::Column::Id)
+            .all(db)
+            .await
+    }
+
+    // Get all URL's submitted from within a stored range, such as a CIDR ban's.
+    pub async fn find_urls_in_range(
+        db: &DbConn,
+        begin: Vec<u8>,
+        end: Vec<u8>,
+    ) -> Result<Vec<url::Model>, DbErr> {
+        Url::find()
+            .filter(url::Column::Ip.between(begin, end))
+            .order_by_asc(url::Column::Id)
+            .all(db)
+            .await
+    }
+
+    // Get all URL's submitted from within a network.
+    pub async fn find_urls_in_network(
+        db: &DbConn,
+        network: IpNetwork,
+    ) -> Result<Vec<url::Model>, DbErr> {
+        let (begin, end) = network_range(network);
+        Self::find_urls_in_range(db, begin, end).await
+    }
+
     // Find a URL filter by its ID.
     pub async fn find_url_filter(db: &DbConn, id: i64) -> Result<Option<url_filter::Model>, DbErr> {
         UrlFilter::find_by_id(id).one(db).await
@@ -88,6 +180,94 @@ impl Query {
             .await
     }
 
+    // Get all GeoIP bans in the database.
+    pub async fn fetch_all_geo_bans(
+        db: &DbConn,
+    ) -> Result<Vec<(geo_ban::Model, Option<user::Model>)>, DbErr> {
+        GeoBan::find()
+            .order_by_asc(geo_ban::Column::Kind)
+            .order_by_asc(geo_ban::Column::Value)
+            .find_also_related(User)
+            .all(db)
+            .await
+    }
+
+    // Get every GeoIP ban that hasn't expired.
+    pub async fn fetch_active_geo_bans(db: &DbConn) -> Result<Vec<geo_ban::Model>, DbErr> {
+        GeoBan::find()
+            .filter(
+                Condition::any()
+                    .add(geo_ban::Column::ExpiresAt.is_null())
+                    .add(geo_ban::Column::ExpiresAt.gt(TimeDateTimeWithTimeZone::now_utc())),
+            )
+            .all(db)
+            .await
+    }
+
+    // Get all CIDR allows in the database.
+    pub async fn fetch_all_cidr_allows(
+        db: &DbConn,
+    ) -> Result<Vec<(cidr_allow::Model, Option<user::Model>)>, DbErr> {
+        CidrAllow::find()
+            .order_by_asc(cidr_allow::Column::RangeBegin)
+            .find_also_related(User)
+            .all(db)
+            .await
+    }
+
+    // Get the ranges of every CIDR allow.
+    pub async fn fetch_cidr_allow_ranges(db: &DbConn) -> Result<Vec<(Vec<u8>, Vec<u8>)>, DbErr> {
+        CidrAllow::find()
+            .select_only()
+            .column(cidr_allow::Column::RangeBegin)
+            .column(cidr_allow::Column::RangeEnd)
+            .order_by_asc(cidr_allow::Column::RangeBegin)
+            .into_tuple()
+            .all(db)
+            .await
+    }
+
+    // Get the ranges of every CIDR ban with the given scope that hasn't expired.
+    pub async fn fetch_active_cidr_ranges(
+        db: &DbConn,
+        scope: BanScope,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, DbErr> {
+        CidrBan::find()
+            .select_only()
+            .column(cidr_ban::Column::RangeBegin)
+            .column(cidr_ban::Column::RangeEnd)
+            .filter(cidr_ban::Column::Scope.eq(scope))
+            .filter(
+                Condition::any()
+                    .add(cidr_ban::Column::ExpiresAt.is_null())
+                    .add(cidr_ban::Column::ExpiresAt.gt(TimeDateTimeWithTimeZone::now_utc())),
+            )
+            .order_by_asc(cidr_ban::Column::RangeBegin)
+            .into_tuple()
+            .all(db)
+            .await
+    }
+
+    // Get the ID, scope, expiry, and range of every CIDR ban that hasn't expired.
+    pub async fn fetch_active_cidr_bans(db: &DbConn) -> Result<Vec<BanRange>, DbErr> {
+        CidrBan::find()
+            .select_only()
+            .column(cidr_ban::Column::Id)
+            .column(cidr_ban::Column::Scope)
+            .column(cidr_ban::Column::ExpiresAt)
+            .column(cidr_ban::Column::RangeBegin)
+            .column(cidr_ban::Column::RangeEnd)
+            .filter(
+                Condition::any()
+                    .add(cidr_ban::Column::ExpiresAt.is_null())
+                    .add(cidr_ban::Column::ExpiresAt.gt(TimeDateTimeWithTimeZone::now_utc())),
+            )
+            .order_by_asc(cidr_ban::Column::RangeBegin)
+            .into_tuple()
+            .all(db)
+            .await
+    }
+
     // Get all URL filters in the database.
     // TODO: pagination?
     pub async fn fetch_all_url_filters(
@@ -100,23 +280,87 @@ impl Query {
             .await
     }
 
-    // Check if an IP is banned or not.
-    pub async fn check_ip_ban(db: &DbConn, addr: IpAddr) -> Result<bool, DbErr> {
-        let octets: [u8; 16] = match addr {
-            IpAddr::V4(i) => i.to_ipv6_mapped(),
-            IpAddr::V6(i) => i,
-        }
-        .octets();
+    // Get what a CIDR ban was before each time it was edited, newest first, with who changed it.
+    pub async fn fetch_cidr_ban_history(
+        db: &DbConn,
+        id: i64,
+    ) -> Result<Vec<(cidr_ban_history::Model, Option<user::Model>)>, DbErr> {
+        CidrBanHistory::find()
+            .filter(cidr_ban_history::Column::CidrBanId.eq(id))
+            .order_by_desc(cidr_ban_history::Column::Id)
+            .find_also_related(User)
+            .all(db)
+            .await
+    }
 
-        let count = CidrBan::find()
-            .filter(
-                cidr_ban::Column::RangeBegin
-                    .lte(octets.to_vec())
-                    .and(cidr_ban::Column::RangeEnd.gte(octets.to_vec())),
-            )
-            .count(db)
-            .await?;
+    // Get what a URL filter was before each time it was edited, newest first, with who changed it.
+    pub async fn fetch_url_filter_history(
+        db: &DbConn,
+        id: i64,
+    ) -> Result<Vec<(url_filter_history::Model, Option<user::Model>)>, DbErr> {
+        UrlFilterHistory::find()
+            .filter(url_filter_history::Column::UrlFilterId.eq(id))
+            .order_by_desc(url_filter_history::Column::Id)
+            .find_also_related(User)
+            .all(db)
+            .await
+    }
 
-        Ok(count > 0)
+    // Get all CIDR bans covering an IP, including expired ones that haven't been cleaned up yet.
+    pub async fn find_ip_bans(
+        db: &DbConn,
+        addr: IpAddr,
+    ) -> Result<Vec<(cidr_ban::Model, Option<user::Model>)>, DbErr> {
+        CidrBan::find()
+            .filter(ip_range_condition(
+                cidr_ban::Column::RangeBegin,
+                cidr_ban::Column::RangeEnd,
+                addr,
+            ))
+            .order_by_asc(cidr_ban::Column::RangeBegin)
+            .find_also_related(User)
+            .all(db)
+            .await
     }
+
+    // Get all CIDR bans sharing any addresses with a network, including expired ones that haven't
+    // been cleaned up yet.
+    pub async fn find_overlapping_cidr_bans(
+        db: &DbConn,
+        network: IpNetwork,
+    ) -> Result<Vec<(cidr_ban::Model, Option<user::Model>)>, DbErr> {
+        let (begin, end) = network_range(network);
+        CidrBan::find()
+            .filter(cidr_ban::Column::RangeBegin.lte(end))
+            .filter(cidr_ban::Column::RangeEnd.gte(begin))
+            .order_by_asc(cidr_ban::Column::RangeBegin)
+            .find_also_related(User)
+            .all(db)
+            .await
+    }
+
+    // Get all CIDR allows covering an IP.
+    pub async fn find_ip_allows(
+        db: &DbConn,
+        addr: IpAddr,
+    ) -> Result<Vec<(cidr_allow::Model, Option<user::Model>)>, DbErr> {
+        CidrAllow::find()
+            .filter(ip_range_condition(
+                cidr_allow::Column::RangeBegin,
+                cidr_allow::Column::RangeEnd,
+                addr,
+            ))
+            .order_by_asc(cidr_allow::Column::RangeBegin)
+            .find_also_related(User)
+            .all(db)
+            .await
+    }
+}
+
+// Condition matching stored ranges that cover an IP
+fn ip_range_condition<C: ColumnTrait>(begin: C, end: C, addr: IpAddr) -> Condition {
+    let octets = ip_octets(addr);
+    Condition::all()
+        .add(begin.lte(octets.clone()))
+        .add(end.gte(octets))
 }
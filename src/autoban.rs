@@ -0,0 +1,198 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/autoban.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// Automatically ban clients that keep getting their submissions rejected.
+//
+// Each rejection is a strike against the client's address, or its /64 for IPv6, since those are
+// usually handed out whole. Enough strikes within the window gets the network a temporary ban,
+// attributed to the system user.
+
+use std::{
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use ipnetwork::{IpNetwork, Ipv6Network};
+use moka::future::Cache;
+use sea_orm::{ActiveEnum, DbConn, DbErr, TryIntoModel};
+use time::{Duration, OffsetDateTime};
+use tracing::{debug, info, warn};
+
+use entity::{sea_orm_active_enums::BanScope, user};
+use service::{Mutation, Query};
+
+use crate::{
+    bancache::{BanCache, BanCacheError},
+    env::Vars,
+};
+
+// How many networks we keep strikes for at once
+const MAX_TRACKED: u64 = 10000;
+
+// Prefix strikes are counted against for IPv6
+const IPV6_PREFIX: u8 = 64;
+
+// Password hash of the system user; it isn't a valid hash, so nobody can log in as them
+const LOCKED_PASSWORD_HASH: &str = "!";
+
+#[derive(Debug, thiserror::Error)]
+pub enum AutoBanError {
+    #[error(transparent)]
+    Db(#[from] DbErr),
+
+    #[error(transparent)]
+    BanCache(#[from] BanCacheError),
+
+    #[error("User {0} can log in, so automatic bans can't be attributed to them; set AUTOBAN_USER to another name")]
+    UserInUse(String),
+}
+
+#[derive(Clone)]
+pub struct AutoBan {
+    // Strikes against each network, forgotten when the window is up
+    strikes: Cache<IpNetwork, Arc<AtomicU32>>,
+    threshold: u32,
+    window: Duration,
+    duration: Duration,
+    // Who bans are attributed to; if unset, auto-banning is disabled
+    user: Option<Arc<user::Model>>,
+    db: Arc<DbConn>,
+    bancache: BanCache,
+}
+
+// The network strikes against an address count towards
+fn strike_network(addr: IpAddr) -> IpNetwork {
+    match addr.to_canonical() {
+        IpAddr::V4(addr) => IpNetwork::V4(addr.into()),
+        IpAddr::V6(addr) => {
+            // Should not fail, as the prefix is in range
+            let network = Ipv6Network::new(addr, IPV6_PREFIX).expect("Invalid IPv6 prefix");
+            IpNetwork::V6(
+                Ipv6Network::new(network.network(), IPV6_PREFIX).expect("Invalid IPv6 prefix"),
+            )
+        }
+    }
+}
+
+impl AutoBan {
+    // Set up auto-banning, creating the system user if need be
+    pub(crate) async fn new(
+        db: Arc<DbConn>,
+        bancache: BanCache,
+        env: &Vars,
+    ) -> Result<Self, AutoBanError> {
+        let user = if env.autoban_strikes == 0 {
+            info!("Automatic bans are disabled");
+            None
+        } else if let Some(user) = Query::find_user_by_username(&db, &env.autoban_user).await? {
+            // Don't pin bans on a real account that happens to have the name
+            if user.password_hash != LOCKED_PASSWORD_HASH {
+                return Err(AutoBanError::UserInUse(user.username));
+            }
+            Some(Arc::new(user))
+        } else {
+            warn!(
+                "Creating system user {} for automatic bans",
+                env.autoban_user
+            );
+            let user = Mutation::create_user(&db, &env.autoban_user, LOCKED_PASSWORD_HASH)
+                .await?
+                .try_into_model()?;
+            Some(Arc::new(user))
+        };
+
+        Ok(Self {
+            strikes: Cache::builder()
+                .max_capacity(MAX_TRACKED)
+                .time_to_live(env.autoban_window.unsigned_abs())
+                .build(),
+            threshold: env.autoban_strikes,
+            window: env.autoban_window,
+            duration: env.autoban_duration,
+            user,
+            db,
+            bancache,
+        })
+    }
+
+    // Forget the strikes against an address's network
+    pub(crate) async fn forget(&self, addr: IpAddr) {
+        let network = strike_network(addr);
+        self.strikes.invalidate(&network).await;
+        debug!("Forgot strikes against {network} ({addr})");
+    }
+
+    // Record a strike against an address, banning its network if it's had too many
+    pub(crate) async fn strike(&self, addr: IpAddr, why: &str) -> Result<(), AutoBanError> {
+        let Some(user) = &self.user else {
+            return Ok(());
+        };
+
+        let network = strike_network(addr);
+        let strikes = self
+            .strikes
+            .get_with(network, async { Arc::new(AtomicU32::new(0)) })
+            .await
+            .fetch_add(1, Ordering::Relaxed)
+            + 1;
+        debug!("Strike {strikes} against {network} ({addr}): {why}");
+
+        // Only whoever makes the last strike does the ban
+        if strikes != self.threshold {
+            return Ok(());
+        }
+        self.strikes.invalidate(&network).await;
+
+        // An expired ban of the same network may still be around, which would get in the way
+        let now = OffsetDateTime::now_utc();
+        Mutation::delete_expired_cidr_bans(&self.db, now).await?;
+
+        // There can only be one ban on a network, and whoever set one that's still there gets the
+        // final say
+        if let Some(ban) = Query::find_cidr_ban_by_network(&self.db, network).await? {
+            info!(
+                "Not automatically banning {network} after {strikes} strikes, as it already has \
+                 ban #{} (scope {})",
+                ban.id,
+                ban.scope.to_value()
+            );
+            return Ok(());
+        }
+
+        let reason = format!(
+            "Automatic ban: {strikes} rejected submissions within {}, last: {why}",
+            self.window
+        );
+        Mutation::create_cidr_ban(
+            &self.db,
+            network,
+            BanScope::Submit,
+            Some(reason),
+            user,
+            // Too long to say when is as good as forever
+            now.checked_add(self.duration),
+        )
+        .await?;
+        self.bancache.reload().await?;
+
+        warn!(
+            "Automatically banned {network} for {} after {strikes} strikes",
+            self.duration
+        );
+        Ok(())
+    }
+}
@@ -12,69 +12,314 @@
  * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
  */
 
-use std::{net::IpAddr, sync::Arc};
+use std::{
+    collections::{btree_map::Entry, BTreeMap, HashMap},
+    net::IpAddr,
+    sync::Arc,
+};
 
-use ipnetwork::IpNetwork;
-use moka::future::Cache;
 use sea_orm::{DbConn, DbErr};
-use time::Duration;
+use time::OffsetDateTime;
+use tokio::sync::{Mutex, RwLock};
 use tracing::trace;
 
-use service::Query;
+use entity::{
+    geo_ban,
+    sea_orm_active_enums::{BanScope, GeoKind},
+};
+use service::{BanRange, Query};
 
-// This caches IP bans/allows so we don't hit the database so much.
+use crate::{
+    geoip::GeoIp,
+    util::net::{ipaddr_to_int, range_to_int, AddressError},
+};
+
+// This keeps every ban and allow in memory, so checking an IP never has to go to the database.
+//
+// The address space is cut into segments wherever a ban or allow begins or ends, and each segment
+// records the bans in effect across all of it, with allows already taken into account. Checking
+// an IP is then a binary search for its segment.
+//
+// Bans that expire are left in the index until the next reload, and skipped when they're found.
+//
+// GeoIP bans are looked up by the IP's country and AS number, unless an allow covers the IP.
 
 #[derive(Debug, thiserror::Error)]
 pub enum BanCacheError {
     #[error(transparent)]
     Db(#[from] DbErr),
+
+    #[error(transparent)]
+    Address(#[from] AddressError),
+}
+
+// ID's of the bans covering an IP, with their scopes
+pub type BanMatches = Arc<[(i64, BanScope)]>;
+
+// Every ban covering an IP
+#[derive(Debug, Default)]
+pub struct Bans {
+    pub(crate) cidr: BanMatches,
+    pub(crate) geo: Vec<(i64, BanScope)>,
 }
 
-#[derive(Clone)]
+// A segment of the address space
+#[derive(Debug, PartialEq)]
+struct Segment {
+    // The CIDR bans in effect
+    bans: BanMatches,
+    // True if any allow covers the segment
+    allowed: bool,
+}
+
+#[derive(Debug)]
+struct BanIndex {
+    // Where each segment begins, and what's in effect until the next one begins, in order
+    segments: Vec<(u128, Segment)>,
+    // GeoIP bans by country code and by AS number
+    countries: HashMap<String, Vec<(i64, BanScope)>>,
+    asns: HashMap<u32, Vec<(i64, BanScope)>>,
+    // When bans expire, by ID, for the ones that do
+    cidr_expiries: HashMap<i64, OffsetDateTime>,
+    geo_expiries: HashMap<i64, OffsetDateTime>,
+}
+
+impl BanIndex {
+    fn new(
+        bans: Vec<BanRange>,
+        geo_bans: Vec<geo_ban::Model>,
+        allows: Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> Result<Self, BanCacheError> {
+        let cidr_expiries = bans
+            .iter()
+            .filter_map(|(id, _, expires_at, _, _)| expires_at.map(|expires_at| (*id, expires_at)))
+            .collect();
+        let geo_expiries = geo_bans
+            .iter()
+            .filter_map(|ban| ban.expires_at.map(|expires_at| (ban.id, expires_at)))
+            .collect();
+
+        let mut countries: HashMap<String, Vec<(i64, BanScope)>> = HashMap::new();
+        let mut asns: HashMap<u32, Vec<(i64, BanScope)>> = HashMap::new();
+        for ban in geo_bans {
+            match ban.kind {
+                GeoKind::Country => countries
+                    .entry(ban.value)
+                    .or_default()
+                    .push((ban.id, ban.scope)),
+                GeoKind::Asn => {
+                    // Stored values are checked when they're added
+                    if let Ok(asn) = ban.value.parse() {
+                        asns.entry(asn).or_default().push((ban.id, ban.scope));
+                    }
+                }
+            }
+        }
+
+        // Every range, with the ban it belongs to; allows don't have one
+        let mut ranges = Vec::with_capacity(bans.len() + allows.len());
+        for (id, scope, _, begin, end) in bans {
+            ranges.push((range_to_int(begin, end)?, Some((id, scope))));
+        }
+        for (begin, end) in allows {
+            ranges.push((range_to_int(begin, end)?, None));
+        }
+
+        // Ranges start at their beginning, and stop just past their end
+        let mut events = Vec::with_capacity(ranges.len() * 2);
+        for (i, ((begin, end), _)) in ranges.iter().enumerate() {
+            events.push((*begin, true, i));
+            // Ranges running to the end of the address space never stop
+            if let Some(stop) = end.checked_add(1) {
+                events.push((stop, false, i));
+            }
+        }
+        events.sort_unstable();
+
+        // Bans in effect by ID, with their scope and the size of their range
+        let mut active_bans: BTreeMap<i64, (BanScope, u128)> = BTreeMap::new();
+        // How many allows of each size are in effect
+        let mut active_allows: BTreeMap<u128, usize> = BTreeMap::new();
+        let mut segments: Vec<(u128, Segment)> = Vec::new();
+
+        let mut events = events.into_iter().peekable();
+        while let Some(&(point, _, _)) = events.peek() {
+            // Take in everything starting or stopping here before working out what's in effect
+            while let Some((_, starting, i)) = events.next_if(|&(p, _, _)| p == point) {
+                let ((begin, end), ban) = ranges[i];
+                let size = end - begin;
+                match (ban, starting) {
+                    (Some((id, scope)), true) => {
+                        active_bans.insert(id, (scope, size));
+                    }
+                    (Some((id, _)), false) => {
+                        active_bans.remove(&id);
+                    }
+                    (None, true) => *active_allows.entry(size).or_default() += 1,
+                    (None, false) => {
+                        if let Entry::Occupied(mut count) = active_allows.entry(size) {
+                            *count.get_mut() -= 1;
+                            if *count.get() == 0 {
+                                count.remove();
+                            }
+                        }
+                    }
+                }
+            }
+
+            // The most specific ban or allow wins, and allows win ties
+            let narrowest_allow = active_allows.keys().next().copied();
+            let segment = Segment {
+                bans: active_bans
+                    .iter()
+                    .filter(|(_, (_, size))| narrowest_allow.map_or(true, |allow| *size < allow))
+                    .map(|(id, (scope, _))| (*id, *scope))
+                    .collect(),
+                allowed: narrowest_allow.is_some(),
+            };
+
+            // Neighbouring segments with the same bans may as well be one
+            if segments.last().map_or(true, |(_, last)| *last != segment) {
+                segments.push((point, segment));
+            }
+        }
+
+        Ok(Self {
+            segments,
+            countries,
+            asns,
+            cidr_expiries,
+            geo_expiries,
+        })
+    }
+
+    // True if there are GeoIP bans to look IP's up for
+    fn has_geo_bans(&self) -> bool {
+        !self.countries.is_empty() || !self.asns.is_empty()
+    }
+
+    // Find the CIDR bans in effect for an IP, and whether any allow covers it
+    fn matches(&self, ip: IpAddr, now: OffsetDateTime) -> (BanMatches, bool) {
+        let key = ipaddr_to_int(ip);
+        let pos = self.segments.partition_point(|(begin, _)| *begin <= key);
+        pos.checked_sub(1).map_or_else(
+            || (Arc::from([]), false),
+            |pos| {
+                let segment = &self.segments[pos].1;
+                let expired = |(id, _): &(i64, BanScope)| {
+                    self.cidr_expiries
+                        .get(id)
+                        .is_some_and(|expires_at| *expires_at <= now)
+                };
+                // Only copy the bans if there are expired ones to leave out
+                let bans = if segment.bans.iter().any(expired) {
+                    segment
+                        .bans
+                        .iter()
+                        .filter(|ban| !expired(ban))
+                        .copied()
+                        .collect()
+                } else {
+                    segment.bans.clone()
+                };
+                (bans, segment.allowed)
+            },
+        )
+    }
+
+    // Find the GeoIP bans for a country and AS number
+    fn geo_matches(
+        &self,
+        country: Option<&str>,
+        asn: Option<u32>,
+        now: OffsetDateTime,
+    ) -> Vec<(i64, BanScope)> {
+        let mut bans: Vec<(i64, BanScope)> = country
+            .and_then(|country| self.countries.get(country))
+            .into_iter()
+            .chain(asn.and_then(|asn| self.asns.get(&asn)))
+            .flatten()
+            .filter(|(id, _)| {
+                !self
+                    .geo_expiries
+                    .get(id)
+                    .is_some_and(|expires_at| *expires_at <= now)
+            })
+            .copied()
+            .collect();
+        bans.sort_unstable_by_key(|(id, _)| *id);
+        bans
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct BanCache {
-    cache: Cache<IpAddr, bool>,
+    // This makes sure we can still clone, yet we still point to the same index.
+    // The index is rebuilt from scratch whenever a ban or allow is added or removed.
+    index: Arc<RwLock<BanIndex>>,
+    // Held while reloading, so a slow reload can't overwrite a newer one
+    reloading: Arc<Mutex<()>>,
     db: Arc<DbConn>,
+    geoip: GeoIp,
 }
 
 impl BanCache {
-    pub(crate) fn new(db: Arc<DbConn>, entries: u64, ttl: Duration, idle: Duration) -> Self {
-        Self {
-            // XXX - should these cache parameters be configurable?
-            cache: Cache::builder()
-                .max_capacity(entries)
-                .time_to_live(ttl.unsigned_abs())
-                .time_to_idle(idle.unsigned_abs())
-                .support_invalidation_closures()
-                .build(),
-            db,
-        }
+    // Load every ban that hasn't expired, and every allow, from the database
+    async fn load_index(db: &DbConn) -> Result<BanIndex, BanCacheError> {
+        let bans = Query::fetch_active_cidr_bans(db).await?;
+        let geo_bans = Query::fetch_active_geo_bans(db).await?;
+        let allows = Query::fetch_cidr_allow_ranges(db).await?;
+        let index = BanIndex::new(bans, geo_bans, allows)?;
+        trace!("Loaded ban index ({} segments)", index.segments.len());
+        Ok(index)
     }
 
-    // Check for a ban, if it's not present, then check the database.
-    pub(crate) async fn check_ban(&self, ip: IpAddr) -> Result<bool, BanCacheError> {
-        if let Some(result) = self.cache.get(&ip).await {
-            trace!("{ip}: got a cache hit (banned: {result})");
-            Ok(result)
-        } else {
-            let result = Query::check_ip_ban(&self.db, ip).await?;
-            trace!("{ip}: got a cache miss (banned: {result})");
-            self.cache.insert(ip, result).await;
-            Ok(result)
-        }
+    // Create a new BanCache instance and load the bans from the database.
+    pub(crate) async fn new(db: Arc<DbConn>, geoip: GeoIp) -> Result<Self, BanCacheError> {
+        let index = Self::load_index(&db).await?;
+
+        Ok(Self {
+            index: Arc::new(RwLock::new(index)),
+            reloading: Arc::default(),
+            db,
+            geoip,
+        })
     }
 
-    // Invalidate a ban in the cache
-    // NOTE: this must be called after a ban is created *or* deleted.
-    pub(crate) fn invalidate(&self, network: IpNetwork) {
-        trace!("Invalidating cache for {network}");
-        self.cache
-            .invalidate_entries_if(move |k, _| network.contains(*k))
-            .expect("Could not invalidate cache");
+    // Sync the index with the database.
+    // NOTE: this must be called after a ban or allow is created *or* deleted.
+    pub(crate) async fn reload(&self) -> Result<(), BanCacheError> {
+        let reloading = self.reloading.lock().await;
+        // Build the new index before taking the lock, so checks aren't held up
+        let index = Self::load_index(&self.db).await?;
+        *self.index.write().await = index;
+        drop(reloading);
+        Ok(())
     }
 
-    // Invalidate all bans in the cache
-    pub(crate) fn invalidate_all(&self) {
-        trace!("Invalidating entire ban cache");
-        self.cache.invalidate_all();
+    // Check which bans cover an IP.
+    // Returns the ID's and scopes of the bans covering the IP; if there are none, it isn't banned.
+    pub(crate) async fn check_ban(&self, ip: IpAddr) -> Result<Bans, BanCacheError> {
+        let now = OffsetDateTime::now_utc();
+        let (cidr, allowed, has_geo_bans) = {
+            let index = self.index.read().await;
+            let (cidr, allowed) = index.matches(ip, now);
+            (cidr, allowed, index.has_geo_bans())
+        };
+
+        // Only bother looking the IP up if it could make a difference
+        let geo = if has_geo_bans && !allowed {
+            let location = self.geoip.lookup(ip).await;
+            self.index
+                .read()
+                .await
+                .geo_matches(location.country.as_deref(), location.asn, now)
+        } else {
+            Vec::new()
+        };
+
+        let bans = Bans { cidr, geo };
+        trace!("{ip}: bans {bans:?}");
+        Ok(bans)
     }
 }
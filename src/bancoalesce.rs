@@ -0,0 +1,201 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/bancoalesce.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// Find and merge redundant CIDR bans.
+//
+// Bans only get coalesced with bans that block the same things until the same time, so merging
+// never changes who is banned from what, or for how long. Bans touching an allow are left alone,
+// as allows only win over bans that aren't more specific than them, and merging changes that.
+// Imported bans are left alone too; their blocklist replaces them wholesale on the next import, so
+// merging them would leave them around after the list drops them, and doubled up after it doesn't.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use ipnetwork::IpNetwork;
+use sea_orm::{ActiveEnum, DbConn, DbErr};
+use time::OffsetDateTime;
+
+use entity::{cidr_ban, sea_orm_active_enums::BanScope};
+use service::{Mutation, Query};
+
+use crate::util::net::{
+    int_range_networks, ipaddr_to_int, range_to_int, AddressError, NetworkPrefixError,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum CoalesceError {
+    #[error(transparent)]
+    Db(#[from] DbErr),
+
+    #[error(transparent)]
+    Address(#[from] AddressError),
+
+    #[error(transparent)]
+    NetworkPrefix(#[from] NetworkPrefixError),
+}
+
+// Bans with their stored ranges as integers
+type IntBan = (u128, u128, cidr_ban::Model);
+
+// A set of bans that can be replaced by fewer of them
+#[derive(Debug)]
+pub struct Coalesced {
+    // Bans made redundant
+    pub(crate) remove: Vec<cidr_ban::Model>,
+    // Bans already covering part of the result, which stay as they are
+    pub(crate) keep: Vec<cidr_ban::Model>,
+    // Networks to ban in place of the removed bans
+    pub(crate) add: Vec<IpNetwork>,
+    scope: BanScope,
+    expires_at: Option<OffsetDateTime>,
+}
+
+// The stored range of a network, as integers
+fn network_ints(network: IpNetwork) -> (u128, u128) {
+    (
+        ipaddr_to_int(network.network()),
+        ipaddr_to_int(network.broadcast()),
+    )
+}
+
+// The fewest networks covering every range given, which must overlap or touch one another
+pub fn union_networks(
+    ranges: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+) -> Result<Vec<IpNetwork>, CoalesceError> {
+    let mut union: Option<(u128, u128)> = None;
+    for (begin, end) in ranges {
+        let (begin, end) = range_to_int(begin, end)?;
+        union = Some(union.map_or((begin, end), |(b, e)| (b.min(begin), e.max(end))));
+    }
+
+    match union {
+        Some((begin, end)) => Ok(int_range_networks(begin, end)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+// Work out which bans can be coalesced, without changing anything
+pub async fn plan(db: &DbConn) -> Result<Vec<Coalesced>, CoalesceError> {
+    let now = OffsetDateTime::now_utc();
+    let allows = Query::fetch_cidr_allow_ranges(db)
+        .await?
+        .into_iter()
+        .map(|(begin, end)| range_to_int(begin, end))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Both ends of a range are unique, so we can't add a ban starting or ending where another does
+    let mut begins = HashMap::new();
+    let mut ends = HashMap::new();
+    let mut groups: BTreeMap<_, Vec<IntBan>> = BTreeMap::new();
+    for (ban, _) in Query::fetch_all_cidr_bans(db).await? {
+        let (begin, end) = range_to_int(ban.range_begin.clone(), ban.range_end.clone())?;
+        begins.insert(begin, ban.id);
+        ends.insert(end, ban.id);
+
+        if ban.expires_at.is_some_and(|expires_at| expires_at <= now)
+            || ban.source.is_some()
+            || allows.iter().any(|&(b, e)| b <= end && e >= begin)
+        {
+            continue;
+        }
+        groups
+            .entry((ban.scope.to_value(), ban.expires_at))
+            .or_default()
+            .push((begin, end, ban));
+    }
+
+    let mut plan = Vec::new();
+    for (_, mut bans) in groups {
+        bans.sort_unstable_by_key(|&(begin, end, _)| (begin, end));
+
+        // Split into clusters of bans that overlap or touch
+        let mut clusters: Vec<(u128, u128, Vec<IntBan>)> = Vec::new();
+        for (begin, end, ban) in bans {
+            match clusters.last_mut() {
+                Some((_, last_end, members)) if begin <= last_end.saturating_add(1) => {
+                    *last_end = (*last_end).max(end);
+                    members.push((begin, end, ban));
+                }
+                _ => clusters.push((begin, end, vec![(begin, end, ban)])),
+            }
+        }
+
+        for (begin, end, members) in clusters.into_iter().filter(|(_, _, m)| m.len() > 1) {
+            let networks = int_range_networks(begin, end)?;
+            let wanted: HashSet<_> = networks.iter().map(|&n| network_ints(n)).collect();
+            let existing: HashSet<_> = members.iter().map(|&(b, e, _)| (b, e)).collect();
+
+            let (keep, remove): (Vec<_>, Vec<_>) = members
+                .into_iter()
+                .partition(|(b, e, _)| wanted.contains(&(*b, *e)));
+            if remove.is_empty() {
+                continue;
+            }
+            let removed: HashSet<i64> = remove.iter().map(|(_, _, ban)| ban.id).collect();
+            let add: Vec<IpNetwork> = networks
+                .into_iter()
+                .filter(|&n| !existing.contains(&network_ints(n)))
+                .collect();
+
+            // Leave it to an admin if something else is in the way
+            let blocked = |ids: &HashMap<u128, i64>, at: u128| {
+                ids.get(&at).is_some_and(|id| !removed.contains(id))
+            };
+            if add.iter().any(|&n| {
+                let (b, e) = network_ints(n);
+                blocked(&begins, b) || blocked(&ends, e)
+            }) {
+                continue;
+            }
+            for &network in &add {
+                let (b, e) = network_ints(network);
+                // Not a real ID, so nothing else can take these spots
+                begins.insert(b, 0);
+                ends.insert(e, 0);
+            }
+
+            let scope = remove[0].2.scope;
+            let expires_at = remove[0].2.expires_at;
+            plan.push(Coalesced {
+                remove: remove.into_iter().map(|(_, _, ban)| ban).collect(),
+                keep: keep.into_iter().map(|(_, _, ban)| ban).collect(),
+                add,
+                scope,
+                expires_at,
+            });
+        }
+    }
+
+    Ok(plan)
+}
+
+// Carry out a plan
+pub async fn apply(db: &DbConn, plan: &[Coalesced]) -> Result<(), CoalesceError> {
+    for coalesced in plan {
+        let ids: Vec<i64> = coalesced.remove.iter().map(|ban| ban.id).collect();
+        let from: Vec<String> = ids.iter().map(|id| format!("#{id}")).collect();
+        Mutation::merge_cidr_bans(
+            db,
+            &ids,
+            &coalesced.add,
+            coalesced.scope,
+            Some(format!("Coalesced from bans {}", from.join(", "))),
+            coalesced.remove[0].user_created_id,
+            coalesced.expires_at,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,257 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/banexport.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// Export bans for firewalls and proxies, so banned traffic can be dropped before it gets here.
+//
+// Overlapping and adjacent bans are merged, then split into as few prefixes as possible.
+// Allows punch holes in any ban they're at least as specific as, the same way they do when
+// checking an IP, so firewalls don't drop traffic we'd let through.
+// Only site-wide bans are exported, as anything narrower would block too much.
+
+use std::{
+    fmt::Write,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+use clap::ValueEnum;
+use ipnetwork::IpNetwork;
+use sea_orm::{DbConn, DbErr};
+use serde::Deserialize;
+
+use entity::sea_orm_active_enums::BanScope;
+use service::Query;
+
+use crate::util::net::{find_networks, range_to_int, AddressError, NetworkPrefixError};
+
+// Name of the sets we export, suffixed with the address family
+const SET_NAME: &str = "shadyurl";
+
+// IPv4 addresses are stored mapped into IPv6, in this range
+const MAPPED_BEGIN: u128 = 0xffff_0000_0000;
+const MAPPED_END: u128 = 0xffff_ffff_ffff;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// One prefix per line
+    Plain,
+    /// nftables set definitions, to include inside a table
+    Nftables,
+    /// ipset restore file
+    Ipset,
+    /// nginx deny directives, to include in an http, server, or location block
+    Nginx,
+}
+
+// Every format, in the order we show them
+pub const FORMATS: [Format; 4] = [
+    Format::Plain,
+    Format::Nftables,
+    Format::Ipset,
+    Format::Nginx,
+];
+
+impl Format {
+    // Name used in URL's and on the command line
+    pub(crate) const fn name(self) -> &'static str {
+        match self {
+            Self::Plain => "plain",
+            Self::Nftables => "nftables",
+            Self::Ipset => "ipset",
+            Self::Nginx => "nginx",
+        }
+    }
+
+    // Suggested file name for downloads
+    pub(crate) const fn file_name(self) -> &'static str {
+        match self {
+            Self::Plain => "shadyurl-bans.txt",
+            Self::Nftables => "shadyurl-bans.nft",
+            Self::Ipset => "shadyurl-bans.ipset",
+            Self::Nginx => "shadyurl-bans.conf",
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error(transparent)]
+    Db(#[from] DbErr),
+
+    #[error(transparent)]
+    Address(#[from] AddressError),
+
+    #[error(transparent)]
+    NetworkPrefix(#[from] NetworkPrefixError),
+}
+
+// Sort ranges, and merge any that overlap or touch
+fn merge_ranges(mut ranges: Vec<(u128, u128)>) -> Vec<(u128, u128)> {
+    ranges.sort_unstable();
+
+    let mut merged: Vec<(u128, u128)> = Vec::with_capacity(ranges.len());
+    for (begin, end) in ranges {
+        match merged.last_mut() {
+            Some((_, last_end)) if begin <= last_end.saturating_add(1) => {
+                *last_end = (*last_end).max(end);
+            }
+            _ => merged.push((begin, end)),
+        }
+    }
+
+    merged
+}
+
+// Remove the parts of a range covered by any of the holes, which must be sorted and merged
+fn subtract((mut begin, end): (u128, u128), holes: &[(u128, u128)]) -> Vec<(u128, u128)> {
+    let mut pieces = Vec::new();
+    for &(hole_begin, hole_end) in holes {
+        if hole_end < begin {
+            continue;
+        }
+        if hole_begin > end {
+            break;
+        }
+        if hole_begin > begin {
+            pieces.push((begin, hole_begin - 1));
+        }
+        if hole_end >= end {
+            return pieces;
+        }
+        begin = hole_end + 1;
+    }
+
+    pieces.push((begin, end));
+    pieces
+}
+
+// Turn stored ban ranges into the fewest prefixes covering them, minus what's allowed, IPv4 first
+fn aggregate(
+    bans: Vec<(Vec<u8>, Vec<u8>)>,
+    allows: Vec<(Vec<u8>, Vec<u8>)>,
+) -> Result<Vec<IpNetwork>, ExportError> {
+    let allows = allows
+        .into_iter()
+        .map(|(begin, end)| range_to_int(begin, end))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut ranges = Vec::new();
+    for (begin, end) in bans {
+        let (begin, end) = range_to_int(begin, end)?;
+        // Only allows at least as specific as the ban win out over it
+        let holes = merge_ranges(
+            allows
+                .iter()
+                .filter(|&&(b, e)| e - b <= end - begin && b <= end && e >= begin)
+                .copied()
+                .collect(),
+        );
+        ranges.extend(subtract((begin, end), &holes));
+    }
+    let ranges = merge_ranges(ranges);
+
+    let mut v4 = Vec::new();
+    let mut v6 = Vec::new();
+    for (begin, end) in ranges {
+        // Split off the IPv4 part, if there is one
+        if begin <= MAPPED_END && end >= MAPPED_BEGIN {
+            v4.push((begin.max(MAPPED_BEGIN), end.min(MAPPED_END)));
+        }
+        if begin < MAPPED_BEGIN {
+            v6.push((begin, end.min(MAPPED_BEGIN - 1)));
+        }
+        if end > MAPPED_END {
+            v6.push((begin.max(MAPPED_END + 1), end));
+        }
+    }
+
+    let mut networks = Vec::new();
+    for (begin, end) in v4 {
+        // These are within the mapped range, so they fit
+        #[allow(clippy::cast_possible_truncation)]
+        let (begin, end) = (
+            Ipv4Addr::from((begin - MAPPED_BEGIN) as u32),
+            Ipv4Addr::from((end - MAPPED_BEGIN) as u32),
+        );
+        networks.extend(find_networks(begin.into(), end.into())?);
+    }
+    for (begin, end) in v6 {
+        networks.extend(find_networks(
+            Ipv6Addr::from(begin).into(),
+            Ipv6Addr::from(end).into(),
+        )?);
+    }
+
+    Ok(networks)
+}
+
+fn render_nftables(networks: &[IpNetwork]) -> String {
+    let mut out = String::new();
+    for (family, addr_type) in [("v4", "ipv4_addr"), ("v6", "ipv6_addr")] {
+        let elements: Vec<String> = networks
+            .iter()
+            .filter(|n| n.is_ipv4() == (family == "v4"))
+            .map(ToString::to_string)
+            .collect();
+
+        let _ = writeln!(out, "set {SET_NAME}-{family} {{");
+        let _ = writeln!(out, "    type {addr_type}");
+        let _ = writeln!(out, "    flags interval");
+        // nftables doesn't accept an empty element list
+        if !elements.is_empty() {
+            let _ = writeln!(out, "    elements = {{ {} }}", elements.join(", "));
+        }
+        let _ = writeln!(out, "}}");
+    }
+    out
+}
+
+fn render_ipset(networks: &[IpNetwork]) -> String {
+    let mut out = String::new();
+    for (family, inet) in [("v4", "inet"), ("v6", "inet6")] {
+        let name = format!("{SET_NAME}-{family}");
+        let _ = writeln!(out, "create {name} hash:net family {inet} -exist");
+        let _ = writeln!(out, "flush {name}");
+        for network in networks.iter().filter(|n| n.is_ipv4() == (family == "v4")) {
+            let _ = writeln!(out, "add {name} {network}");
+        }
+    }
+    out
+}
+
+// Render bans in the given format
+fn render(format: Format, networks: &[IpNetwork]) -> String {
+    let header = format!("# Generated by ShadyURL: {} prefixes\n", networks.len());
+    match format {
+        Format::Plain => networks.iter().fold(String::new(), |mut out, network| {
+            let _ = writeln!(out, "{network}");
+            out
+        }),
+        Format::Nftables => header + &render_nftables(networks),
+        Format::Ipset => header + &render_ipset(networks),
+        Format::Nginx => networks.iter().fold(header, |mut out, network| {
+            let _ = writeln!(out, "deny {network};");
+            out
+        }),
+    }
+}
+
+// Export every site-wide ban that hasn't expired
+pub async fn export(db: &DbConn, format: Format) -> Result<String, ExportError> {
+    let networks = aggregate(
+        Query::fetch_active_cidr_ranges(db, BanScope::Site).await?,
+        Query::fetch_cidr_allow_ranges(db).await?,
+    )?;
+    Ok(render(format, &networks))
+}
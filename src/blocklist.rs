@@ -0,0 +1,246 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/blocklist.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// Import blocklists published in common formats.
+//
+// Everything imported is tagged with a source name. Importing the same source again replaces
+// whatever it imported last time, so lists can be kept up to date by re-importing them.
+// Lines we can't make sense of are skipped and reported, rather than failing the whole import.
+
+use std::net::IpAddr;
+
+use clap::ValueEnum;
+use ipnetwork::IpNetwork;
+use sea_orm::{DbConn, DbErr};
+use serde::Deserialize;
+
+use entity::{
+    sea_orm_active_enums::{BanScope, FilterKind},
+    user,
+};
+use service::{ImportResult, Mutation};
+
+use crate::urlfilter;
+
+// Names hosts files give to the local machine, which aren't worth filtering
+const LOCAL_NAMES: [&str; 10] = [
+    "localhost",
+    "localhost.localdomain",
+    "local",
+    "broadcasthost",
+    "ip6-localhost",
+    "ip6-loopback",
+    "ip6-localnet",
+    "ip6-mcastprefix",
+    "ip6-allnodes",
+    "ip6-allrouters",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Hosts file, as in "0.0.0.0 example.com"; each host is filtered exactly
+    Hosts,
+    /// One domain per line; each domain is filtered along with its subdomains
+    Domains,
+    /// Spamhaus DROP style, as in "192.0.2.0/24 ; SBL123"; each network is banned
+    Drop,
+}
+
+// Every format, in the order we show them
+pub const FORMATS: [Format; 3] = [Format::Hosts, Format::Domains, Format::Drop];
+
+impl Format {
+    // Name used in forms and on the command line
+    pub(crate) const fn name(self) -> &'static str {
+        match self {
+            Self::Hosts => "hosts",
+            Self::Domains => "domains",
+            Self::Drop => "drop",
+        }
+    }
+
+    // Human-readable description of the format
+    pub(crate) const fn describe(self) -> &'static str {
+        match self {
+            Self::Hosts => "Hosts file (URL filters, exact host)",
+            Self::Domains => "Domain list (URL filters, domain and subdomains)",
+            Self::Drop => "DROP-style CIDR list (CIDR bans)",
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BlocklistError {
+    #[error(transparent)]
+    Db(#[from] DbErr),
+
+    #[error("Source name cannot be empty")]
+    NoSource,
+
+    // Importing nothing would silently remove everything from the source
+    #[error("No entries found in blocklist")]
+    NoEntries,
+}
+
+// Entries parsed out of a blocklist
+#[derive(Debug, Default)]
+struct Parsed {
+    filters: Vec<String>,
+    bans: Vec<(IpNetwork, Option<String>)>,
+    // Line numbers that couldn't be parsed
+    invalid: Vec<usize>,
+}
+
+// The outcome of an import
+#[derive(Debug)]
+pub struct ImportReport {
+    pub(crate) result: ImportResult,
+    pub(crate) invalid: Vec<usize>,
+}
+
+// Strip a comment beginning with any of the given characters
+fn strip_comment<'a>(line: &'a str, markers: &[char]) -> &'a str {
+    line.find(markers).map_or(line, |pos| &line[..pos]).trim()
+}
+
+fn parse_hosts(text: &str) -> Parsed {
+    let mut parsed = Parsed::default();
+    for (lineno, line) in text.lines().enumerate() {
+        let line = strip_comment(line, &['#']);
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        if fields
+            .next()
+            .and_then(|ip| ip.parse::<IpAddr>().ok())
+            .is_none()
+        {
+            parsed.invalid.push(lineno + 1);
+            continue;
+        }
+
+        for host in fields.filter(|host| !LOCAL_NAMES.contains(host)) {
+            match urlfilter::normalize(FilterKind::Host, host) {
+                Ok(host) => parsed.filters.push(host),
+                Err(_) => parsed.invalid.push(lineno + 1),
+            }
+        }
+    }
+
+    parsed
+}
+
+fn parse_domains(text: &str) -> Parsed {
+    let mut parsed = Parsed::default();
+    for (lineno, line) in text.lines().enumerate() {
+        // Adblock-style lists use ! for comments
+        let line = strip_comment(line, &['#', '!']);
+        if line.is_empty() {
+            continue;
+        }
+
+        // Subdomains are always included, so wildcards add nothing
+        let domain = line.trim_start_matches("*.").trim_start_matches('.');
+        match urlfilter::normalize(FilterKind::Domain, domain) {
+            Ok(domain) => parsed.filters.push(domain),
+            Err(_) => parsed.invalid.push(lineno + 1),
+        }
+    }
+
+    parsed
+}
+
+fn parse_drop(text: &str) -> Parsed {
+    let mut parsed = Parsed::default();
+    for (lineno, line) in text.lines().enumerate() {
+        let (network, comment) = line.split_once(';').unwrap_or((line, ""));
+        let network = strip_comment(network, &['#']);
+        if network.is_empty() {
+            continue;
+        }
+
+        match network.parse::<IpNetwork>() {
+            Ok(network) => {
+                let comment = comment.trim();
+                parsed
+                    .bans
+                    .push((network, (!comment.is_empty()).then(|| comment.to_string())));
+            }
+            Err(_) => parsed.invalid.push(lineno + 1),
+        }
+    }
+
+    parsed
+}
+
+// Import a blocklist, replacing anything previously imported from the same source
+pub async fn import(
+    db: &DbConn,
+    format: Format,
+    source: &str,
+    text: &str,
+    user: &user::Model,
+) -> Result<ImportReport, BlocklistError> {
+    let source = source.trim();
+    if source.is_empty() {
+        return Err(BlocklistError::NoSource);
+    }
+
+    let parsed = match format {
+        Format::Hosts => parse_hosts(text),
+        Format::Domains => parse_domains(text),
+        Format::Drop => parse_drop(text),
+    };
+    if parsed.filters.is_empty() && parsed.bans.is_empty() {
+        return Err(BlocklistError::NoEntries);
+    }
+
+    let reason = format!("Imported from {source}");
+    let result = match format {
+        Format::Hosts | Format::Domains => {
+            let kind = if format == Format::Hosts {
+                FilterKind::Host
+            } else {
+                FilterKind::Domain
+            };
+            let filters = parsed
+                .filters
+                .into_iter()
+                .map(|filter| (filter, reason.clone()))
+                .collect();
+            Mutation::replace_url_filters(db, source, kind, filters, user).await?
+        }
+        Format::Drop => {
+            let bans = parsed
+                .bans
+                .into_iter()
+                .map(|(network, comment)| {
+                    let reason =
+                        comment.map_or_else(|| reason.clone(), |c| format!("{reason}: {c}"));
+                    (network, reason)
+                })
+                .collect();
+            // Imported bans block submissions, like bans always used to
+            Mutation::replace_cidr_bans(db, source, BanScope::Submit, bans, user).await?
+        }
+    };
+
+    Ok(ImportReport {
+        result,
+        invalid: parsed.invalid,
+    })
+}
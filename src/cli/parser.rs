@@ -12,11 +12,17 @@
  * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
  */
 
+use std::path::PathBuf;
+
 use clap::{Args, Parser, Subcommand};
 
-use crate::cli::subcommands::{
-    AddUserSubcommand, ChangePasswordSubcommand, CliSubcommand, DeleteUserSubcommand,
-    GenerateKeySubcommand, RunSubcommand,
+use crate::{
+    banexport, blocklist,
+    cli::subcommands::{
+        AddUserSubcommand, ChangePasswordSubcommand, CheckWordsSubcommand, CliSubcommand,
+        CoalesceBansSubcommand, DeleteUserSubcommand, ExportBansSubcommand, GenerateKeySubcommand,
+        GenerateSubcommand, ImportBlocklistSubcommand, RunSubcommand,
+    },
 };
 
 // For commands that only take a username as an argument
@@ -25,6 +31,85 @@ pub struct UsernameArgument {
     pub username: String,
 }
 
+// Arguments for sampling the shady name generator
+#[derive(Debug, Clone, Args)]
+pub struct GenerateArguments {
+    /// Number of sample names to print
+    #[arg(short = 'n', long, default_value_t = 10)]
+    pub count: usize,
+
+    /// Number of names to generate for the statistics
+    #[arg(long, default_value_t = 100_000)]
+    pub samples: usize,
+
+    /// Number of links to estimate the collision probability for
+    #[arg(long, default_value_t = 1_000_000)]
+    pub table_size: u64,
+
+    /// Seed the generator, for reproducible output
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Put names inside of fake directories
+    #[arg(long)]
+    pub directories: bool,
+
+    /// Follow names with a fake query string
+    #[arg(long)]
+    pub query: bool,
+}
+
+// Arguments for checking the word lists against blocking rules
+#[derive(Debug, Clone, Args)]
+pub struct CheckWordsArguments {
+    /// Directory of blocking rule files (defaults to `WORD_RULES_DIR`)
+    #[arg(long)]
+    pub rules: Option<PathBuf>,
+
+    /// Number of generated links to check
+    #[arg(long, default_value_t = 10_000)]
+    pub samples: usize,
+}
+
+// Arguments for importing a blocklist
+#[derive(Debug, Clone, Args)]
+pub struct ImportBlocklistArguments {
+    /// Format of the blocklist
+    #[arg(long, value_enum)]
+    pub format: blocklist::Format,
+
+    /// Name to tag imported entries with; importing the same source again replaces them
+    #[arg(long)]
+    pub source: String,
+
+    /// User to record as having added the entries
+    #[arg(long)]
+    pub user: String,
+
+    /// Blocklist file to import
+    pub path: PathBuf,
+}
+
+// Arguments for exporting bans
+#[derive(Debug, Clone, Args)]
+pub struct ExportBansArguments {
+    /// Format to export bans in
+    #[arg(long, value_enum, default_value_t = banexport::Format::Plain)]
+    pub format: banexport::Format,
+
+    /// File to write to (defaults to standard output)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+// Arguments for coalescing redundant bans
+#[derive(Debug, Clone, Args)]
+pub struct CoalesceBansArguments {
+    /// Only show what would be coalesced, without changing anything
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
 #[derive(Parser)]
 struct Cli {
     #[command(subcommand)]
@@ -38,6 +123,11 @@ pub enum Commands {
     DeleteUser(UsernameArgument),
     ChangePassword(UsernameArgument),
     GenerateKey,
+    Generate(GenerateArguments),
+    CheckWords(CheckWordsArguments),
+    ImportBlocklist(ImportBlocklistArguments),
+    ExportBans(ExportBansArguments),
+    CoalesceBans(CoalesceBansArguments),
 }
 
 pub async fn run_command() -> Result<(), Box<dyn std::error::Error>> {
@@ -59,6 +149,26 @@ pub async fn run_command() -> Result<(), Box<dyn std::error::Error>> {
             GenerateKeySubcommand::run_command(&()).await?;
             Ok(())
         }
+        Some(Commands::Generate(data)) => {
+            GenerateSubcommand::run_command(data).await?;
+            Ok(())
+        }
+        Some(Commands::CheckWords(data)) => {
+            CheckWordsSubcommand::run_command(data).await?;
+            Ok(())
+        }
+        Some(Commands::ImportBlocklist(data)) => {
+            ImportBlocklistSubcommand::run_command(data).await?;
+            Ok(())
+        }
+        Some(Commands::ExportBans(data)) => {
+            ExportBansSubcommand::run_command(data).await?;
+            Ok(())
+        }
+        Some(Commands::CoalesceBans(data)) => {
+            CoalesceBansSubcommand::run_command(data).await?;
+            Ok(())
+        }
         Some(Commands::Run) | None => {
             RunSubcommand::run_command(&()).await?;
             Ok(())
@@ -16,8 +16,13 @@
 
 mod adduser;
 mod changepassword;
+mod checkwords;
+mod coalescebans;
 mod deleteuser;
+mod exportbans;
+mod generate;
 mod generatekey;
+mod importblocklist;
 mod run;
 
 use proctitle::set_title;
@@ -25,12 +30,20 @@ use proctitle::set_title;
 use crate::env::{EnvError, Vars};
 
 // Re-exported
-pub use crate::cli::parser::UsernameArgument;
+pub use crate::cli::parser::{
+    CheckWordsArguments, CoalesceBansArguments, ExportBansArguments, GenerateArguments,
+    ImportBlocklistArguments, UsernameArgument,
+};
 
 pub use adduser::AddUserSubcommand;
 pub use changepassword::ChangePasswordSubcommand;
+pub use checkwords::CheckWordsSubcommand;
+pub use coalescebans::CoalesceBansSubcommand;
 pub use deleteuser::DeleteUserSubcommand;
+pub use exportbans::ExportBansSubcommand;
+pub use generate::GenerateSubcommand;
 pub use generatekey::GenerateKeySubcommand;
+pub use importblocklist::ImportBlocklistSubcommand;
 pub use run::RunSubcommand;
 
 #[async_trait::async_trait]
@@ -0,0 +1,112 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/cli/subcommands/checkwords.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+use std::path::PathBuf;
+
+use crate::{
+    cli::subcommands::{CheckWordsArguments, CliSubcommand},
+    env::Vars,
+    wordcheck::{Report, RuleSet, WordCheckError},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error(transparent)]
+    WordCheck(#[from] WordCheckError),
+
+    #[error("No rules directory given (use --rules or set WORD_RULES_DIR)")]
+    NoRulesDir,
+}
+
+fn print_report(report: &Report) {
+    if report.duplicates.is_empty() {
+        println!("No duplicate fragments found");
+    } else {
+        println!("Duplicate fragments:");
+        for duplicate in &report.duplicates {
+            let entries: Vec<_> = duplicate
+                .entries
+                .iter()
+                .map(|(list, word)| format!("{list} \"{word}\""))
+                .collect();
+            let kind = if duplicate.is_exact() {
+                "repeated"
+            } else {
+                "look alike"
+            };
+            println!("    {kind}: {}", entries.join(", "));
+        }
+    }
+
+    for rule_set in &report.rule_sets {
+        println!();
+        println!("{} ({} rules):", rule_set.name, rule_set.rule_count);
+        println!(
+            "    {} of {} fragments blocked",
+            rule_set.blocked_fragments.len(),
+            report.fragment_count
+        );
+        for (list, word, pattern) in &rule_set.blocked_fragments {
+            println!("        {list} \"{word}\" by \"{pattern}\"");
+        }
+
+        println!(
+            "    {} of {} generated links blocked",
+            rule_set.links_blocked, report.links_sampled
+        );
+        for (link, pattern) in &rule_set.examples {
+            println!("        {link} by \"{pattern}\"");
+        }
+    }
+}
+
+pub struct CheckWordsSubcommand;
+
+#[async_trait::async_trait]
+impl CliSubcommand for CheckWordsSubcommand {
+    type Error = CliError;
+    type PromptUserData = ();
+    type CommandData = CheckWordsArguments;
+
+    fn proc_title() -> String {
+        "shadyurl-rust [check-words]".to_string()
+    }
+
+    fn prompt_user() -> Result<Self::PromptUserData, Self::Error> {
+        Ok(())
+    }
+
+    async fn run(
+        env: Vars,
+        (): Self::PromptUserData,
+        data: &Self::CommandData,
+    ) -> Result<(), Self::Error> {
+        let dir = data
+            .rules
+            .clone()
+            .or_else(|| env.word_rules_dir.clone().map(PathBuf::from))
+            .ok_or(CliError::NoRulesDir)?;
+
+        let rule_sets = RuleSet::load_dir(&dir)?;
+        let report = Report::evaluate(
+            &rule_sets,
+            data.samples,
+            &format!("https://{}/", env.shady_host),
+            env.shady_style(),
+        );
+        print_report(&report);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/cli/subcommands/coalescebans.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+use entity::cidr_ban;
+
+use crate::{
+    bancoalesce::{apply, plan, CoalesceError},
+    cli::subcommands::{CliSubcommand, CoalesceBansArguments},
+    env::Vars,
+    util::net::{int_range_networks, range_to_int},
+};
+
+use service::Database;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error(transparent)]
+    Db(#[from] sea_orm::DbErr),
+
+    #[error(transparent)]
+    Coalesce(#[from] CoalesceError),
+}
+
+// List bans by ID and range
+fn describe(bans: &[cidr_ban::Model]) -> String {
+    bans.iter()
+        .map(|ban| {
+            let range = range_to_int(ban.range_begin.clone(), ban.range_end.clone())
+                .ok()
+                .and_then(|(begin, end)| int_range_networks(begin, end).ok())
+                .map_or_else(
+                    || "invalid range".to_string(),
+                    |networks| {
+                        let networks: Vec<_> = networks.iter().map(ToString::to_string).collect();
+                        networks.join(", ")
+                    },
+                );
+            format!("#{} ({range})", ban.id)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub struct CoalesceBansSubcommand;
+
+#[async_trait::async_trait]
+impl CliSubcommand for CoalesceBansSubcommand {
+    type Error = CliError;
+    type PromptUserData = ();
+    type CommandData = CoalesceBansArguments;
+
+    fn proc_title() -> String {
+        "shadyurl-rust [coalesce-bans]".to_string()
+    }
+
+    fn prompt_user() -> Result<Self::PromptUserData, Self::Error> {
+        Ok(())
+    }
+
+    async fn run(
+        env: Vars,
+        (): Self::PromptUserData,
+        data: &Self::CommandData,
+    ) -> Result<(), Self::Error> {
+        let db = Database::get(&env.database_url).await?;
+        let plan = plan(&db).await?;
+        if plan.is_empty() {
+            println!("No redundant bans found");
+            return Ok(());
+        }
+
+        for coalesced in &plan {
+            let mut into: Vec<String> = coalesced.add.iter().map(ToString::to_string).collect();
+            if !coalesced.keep.is_empty() {
+                into.push(describe(&coalesced.keep));
+            }
+            println!("{} -> {}", describe(&coalesced.remove), into.join(", "));
+        }
+
+        if data.dry_run {
+            println!("Dry run, nothing changed");
+            return Ok(());
+        }
+
+        apply(&db, &plan).await?;
+        let removed: usize = plan.iter().map(|c| c.remove.len()).sum();
+        let added: usize = plan.iter().map(|c| c.add.len()).sum();
+        println!("Coalesced bans: {removed} removed, {added} added");
+
+        // A running server reloads bans periodically
+        println!(
+            "Running servers apply the change at their next ban reload; flush the ban cache in \
+             the admin panel to apply it now."
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/cli/subcommands/exportbans.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+use std::fs::write;
+
+use crate::{
+    banexport::{export, ExportError},
+    cli::subcommands::{CliSubcommand, ExportBansArguments},
+    env::Vars,
+};
+
+use service::Database;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error(transparent)]
+    Db(#[from] sea_orm::DbErr),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Export(#[from] ExportError),
+}
+
+pub struct ExportBansSubcommand;
+
+#[async_trait::async_trait]
+impl CliSubcommand for ExportBansSubcommand {
+    type Error = CliError;
+    type PromptUserData = ();
+    type CommandData = ExportBansArguments;
+
+    fn proc_title() -> String {
+        "shadyurl-rust [export-bans]".to_string()
+    }
+
+    fn prompt_user() -> Result<Self::PromptUserData, Self::Error> {
+        Ok(())
+    }
+
+    async fn run(
+        env: Vars,
+        (): Self::PromptUserData,
+        data: &Self::CommandData,
+    ) -> Result<(), Self::Error> {
+        let db = Database::get(&env.database_url).await?;
+        let exported = export(&db, data.format).await?;
+
+        match &data.output {
+            Some(path) => write(path, exported)?,
+            None => print!("{exported}"),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/cli/subcommands/generate.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+use std::{collections::BTreeMap, convert::Infallible};
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+    cli::subcommands::{CliSubcommand, GenerateArguments},
+    env::Vars,
+    generate::{Generator, Style},
+};
+
+// Width of each bucket in the length distribution
+const LENGTH_BUCKET: usize = 10;
+
+// Prints the length distribution of the sampled names
+fn print_lengths(lengths: &[usize]) {
+    let mut buckets = BTreeMap::new();
+    for len in lengths {
+        *buckets.entry(len / LENGTH_BUCKET).or_insert(0usize) += 1;
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let total = lengths.len() as f64;
+    #[allow(clippy::cast_precision_loss)]
+    let mean = lengths.iter().sum::<usize>() as f64 / total;
+
+    println!(
+        "Length: min {}, max {}, mean {mean:.1}",
+        lengths.iter().min().unwrap_or(&0),
+        lengths.iter().max().unwrap_or(&0)
+    );
+    println!("Length distribution:");
+    for (bucket, count) in buckets {
+        let low = bucket * LENGTH_BUCKET;
+        #[allow(clippy::cast_precision_loss)]
+        let percent = count as f64 / total * 100.0;
+        println!(
+            "    {low:>4}-{:<4} {count:>8} ({percent:.2}%)",
+            low + LENGTH_BUCKET - 1
+        );
+    }
+}
+
+// Prints entropy estimates and the resulting collision odds
+fn print_entropy(info_bits: &[f64], table_size: u64) {
+    #[allow(clippy::cast_precision_loss)]
+    let total = info_bits.len() as f64;
+
+    // The mean information content estimates the Shannon entropy.
+    let mean = info_bits.iter().sum::<f64>() / total;
+    let min = info_bits.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = info_bits.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+    // The mean probability of a name estimates the chance two names are the same, which is what
+    // matters for collisions. This is never more than the Shannon entropy.
+    let collision_entropy = -(info_bits.iter().map(|i| (-i).exp2()).sum::<f64>() / total).log2();
+
+    println!("Entropy per name: mean {mean:.1} bits (min {min:.1}, max {max:.1})");
+    println!("Collision entropy: {collision_entropy:.1} bits");
+
+    #[allow(clippy::cast_precision_loss)]
+    let links = table_size as f64;
+    let p_pair = (-collision_entropy).exp2();
+    let p_next = (links * p_pair).min(1.0);
+    let p_any = -(-links * (links - 1.0) / 2.0 * p_pair).exp_m1();
+
+    println!("With {table_size} links:");
+    println!("    chance the next name collides with an existing one: {p_next:.3e}");
+    println!("    chance of at least one collision between any two names: {p_any:.3e}");
+}
+
+pub struct GenerateSubcommand;
+
+#[async_trait::async_trait]
+impl CliSubcommand for GenerateSubcommand {
+    type Error = Infallible;
+    type PromptUserData = ();
+    type CommandData = GenerateArguments;
+
+    fn proc_title() -> String {
+        "shadyurl-rust [generate]".to_string()
+    }
+
+    fn prompt_user() -> Result<Self::PromptUserData, Self::Error> {
+        Ok(())
+    }
+
+    async fn run(
+        _: Vars,
+        (): Self::PromptUserData,
+        data: &Self::CommandData,
+    ) -> Result<(), Self::Error> {
+        let rng = data
+            .seed
+            .map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+        let style = Style {
+            directories: data.directories,
+            query: data.query,
+        };
+        let mut generator = Generator::new(rng, style);
+
+        if data.count > 0 {
+            println!("Sample names:");
+            for _ in 0..data.count {
+                println!("    {}", generator.generate_shady_name());
+            }
+        }
+
+        if data.samples == 0 {
+            return Ok(());
+        }
+
+        let mut lengths = Vec::with_capacity(data.samples);
+        let mut info_bits = Vec::with_capacity(data.samples);
+        for _ in 0..data.samples {
+            lengths.push(generator.generate_shady_name().to_string().len());
+            info_bits.push(generator.info_bits());
+        }
+
+        println!();
+        println!("Statistics over {} names:", data.samples);
+        print_lengths(&lengths);
+        print_entropy(&info_bits, data.table_size);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/cli/subcommands/importblocklist.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+use std::fs::read_to_string;
+
+use crate::{
+    blocklist::{import, BlocklistError},
+    cli::subcommands::{CliSubcommand, ImportBlocklistArguments},
+    env::Vars,
+};
+
+use service::{Database, Query};
+
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error(transparent)]
+    Db(#[from] sea_orm::DbErr),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Blocklist(#[from] BlocklistError),
+
+    #[error("User not found")]
+    NotFound,
+}
+
+pub struct ImportBlocklistSubcommand;
+
+#[async_trait::async_trait]
+impl CliSubcommand for ImportBlocklistSubcommand {
+    type Error = CliError;
+    type PromptUserData = ();
+    type CommandData = ImportBlocklistArguments;
+
+    fn proc_title() -> String {
+        "shadyurl-rust [import-blocklist]".to_string()
+    }
+
+    fn prompt_user() -> Result<Self::PromptUserData, Self::Error> {
+        Ok(())
+    }
+
+    async fn run(
+        env: Vars,
+        (): Self::PromptUserData,
+        data: &Self::CommandData,
+    ) -> Result<(), Self::Error> {
+        let text = read_to_string(&data.path)?;
+
+        let db = Database::get(&env.database_url).await?;
+        let user = Query::find_user_by_username(&db, &data.user)
+            .await?
+            .ok_or(CliError::NotFound)?;
+
+        let report = import(&db, data.format, &data.source, &text, &user).await?;
+        println!(
+            "Imported {}: {} added, {} removed, {} already present",
+            data.source, report.result.added, report.result.removed, report.result.skipped
+        );
+        if !report.invalid.is_empty() {
+            let lines: Vec<_> = report.invalid.iter().map(ToString::to_string).collect();
+            println!("Skipped invalid lines: {}", lines.join(", "));
+        }
+
+        // A running server keeps bans and filters in memory
+        println!(
+            "Running servers apply imported bans when they next reload them (every \
+             BAN_RELOAD_INTERVAL); flush the URL filter cache in the admin panel to apply imported \
+             filters."
+        );
+
+        Ok(())
+    }
+}
@@ -20,11 +20,17 @@ use serde::{
     de::{Deserializer, Error},
     Deserialize,
 };
-use systemd_duration::time::parse;
 use time::Duration;
 use tracing::error;
 use validator::Validate;
 
+use crate::{
+    generate::Style,
+    ipstorage::IpStorageMode,
+    util::string::parse_duration,
+    validators::{strip_port, validate_interval, ShortenerPolicy, TldCheck, UrlPolicy},
+};
+
 // Routines to get configuration information from the environment and .env file
 
 pub type Key = [u8; 64];
@@ -44,6 +50,84 @@ mod defaults {
         5000
     }
 
+    pub(super) fn url_schemes() -> Vec<String> {
+        [
+            "ftp",
+            "ftps",
+            "gemini",
+            "gopher",
+            "http",
+            "https",
+            "irc",
+            "irc6",
+            "ircs",
+            "jabber",
+            "matrix",
+            "mumble",
+            "mxc",
+            "spotify",
+            "teamspeak",
+            "xmpp",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+
+    pub(super) const fn url_max_length() -> usize {
+        2048
+    }
+
+    pub(super) fn shortener_domains() -> Vec<String> {
+        [
+            "bit.ly",
+            "bl.ink",
+            "buff.ly",
+            "cutt.ly",
+            "goo.gl",
+            "is.gd",
+            "ow.ly",
+            "rb.gy",
+            "rebrand.ly",
+            "s.id",
+            "shorturl.at",
+            "t.co",
+            "t.ly",
+            "tiny.cc",
+            "tinyurl.com",
+            "v.gd",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+
+    pub(super) const fn duration_1m() -> Duration {
+        Duration::minutes(1)
+    }
+
+    pub(super) const fn duration_5m() -> Duration {
+        Duration::minutes(5)
+    }
+
+    pub(super) const fn duration_5s() -> Duration {
+        Duration::seconds(5)
+    }
+
+    pub(super) const fn duration_10m() -> Duration {
+        Duration::minutes(10)
+    }
+
+    pub(super) const fn autoban_strikes() -> u32 {
+        0
+    }
+
+    pub(super) fn autoban_user() -> String {
+        "system".to_string()
+    }
+
+    pub(super) const fn enabled() -> bool {
+        true
+    }
+
     pub(super) fn sitename() -> String {
         "ShadyURL".to_string()
     }
@@ -54,6 +138,14 @@ mod defaults {
         ret
     }
 
+    pub(super) const fn ip_prefix_v4() -> u8 {
+        24
+    }
+
+    pub(super) const fn ip_prefix_v6() -> u8 {
+        48
+    }
+
     pub(super) const fn duration_1d() -> Duration {
         Duration::days(1)
     }
@@ -65,7 +157,8 @@ mod defaults {
 
 mod deserializers {
     use super::{
-        error, parse, Deserialize, Deserializer, Duration, Engine, Error, Key, BASE64_STANDARD,
+        error, parse_duration, Deserialize, Deserializer, Duration, Engine, Error, Key,
+        BASE64_STANDARD,
     };
 
     pub(super) fn duration<'de, D>(d: D) -> Result<Duration, D::Error>
@@ -73,7 +166,7 @@ mod deserializers {
         D: Deserializer<'de>,
     {
         let duration_string = String::deserialize(d)?;
-        let ret: Duration = parse(&duration_string).map_err(|e| {
+        let ret: Duration = parse_duration(&duration_string).map_err(|e| {
             error!("Invalid duration: \"{duration_string}\", error: {e}");
             Error::custom(format!(
                 "Invalid duration received: \"{duration_string}\", error: {e}"
@@ -83,30 +176,52 @@ mod deserializers {
         Ok(ret)
     }
 
-    pub(super) fn csrf_key<'de, D>(d: D) -> Result<Key, D::Error>
+    pub(super) fn optional_duration<'de, D>(d: D) -> Result<Option<Duration>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        duration(d).map(Some)
+    }
+
+    fn key<'de, D>(d: D, what: &str) -> Result<Key, D::Error>
     where
         D: Deserializer<'de>,
     {
         let key_b64 = String::deserialize(d)?;
         let val = BASE64_STANDARD
             .decode(key_b64)
-            .map_err(|e| Error::custom(format!("Could not decode session key: {e}")))?;
+            .map_err(|e| Error::custom(format!("Could not decode {what}: {e}")))?;
 
         let ret: Key = val.try_into().map_err(|v: Vec<u8>| {
             error!(
-                "Session key length was incorrect (expected 64 bytes, got {}",
+                "{what} length was incorrect (expected 64 bytes, got {}",
                 v.len()
             );
             Error::custom(format!(
-                "Could not decode session key: length was incorrect (expected 64 bytes, got {})",
+                "Could not decode {what}: length was incorrect (expected 64 bytes, got {})",
                 v.len()
             ))
         })?;
 
         Ok(ret)
     }
+
+    pub(super) fn csrf_key<'de, D>(d: D) -> Result<Key, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        key(d, "session key")
+    }
+
+    pub(super) fn ip_hmac_key<'de, D>(d: D) -> Result<Option<Key>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        key(d, "IP HMAC key").map(Some)
+    }
 }
 
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Clone, Validate, Deserialize)]
 pub struct Vars {
     #[validate(length(min = 4))]
@@ -116,6 +231,40 @@ pub struct Vars {
     #[serde(default = "defaults::sitename")]
     pub(crate) sitename: String,
 
+    #[serde(default)]
+    pub(crate) shady_directories: bool,
+    #[serde(default)]
+    pub(crate) shady_query: bool,
+
+    #[serde(default = "defaults::url_schemes")]
+    #[validate(length(min = 1))]
+    pub(crate) url_schemes: Vec<String>,
+    #[serde(default = "defaults::url_max_length")]
+    #[validate(range(min = 16))]
+    pub(crate) url_max_length: usize,
+    #[serde(default = "defaults::enabled")]
+    pub(crate) url_allow_ip_hosts: bool,
+    #[serde(default = "defaults::enabled")]
+    pub(crate) url_allow_ports: bool,
+    #[serde(default = "defaults::enabled")]
+    pub(crate) url_allow_credentials: bool,
+    #[serde(default = "defaults::enabled")]
+    pub(crate) url_allow_private: bool,
+    #[serde(default)]
+    pub(crate) url_tld_check: TldCheck,
+
+    #[serde(default = "defaults::shortener_domains")]
+    pub(crate) shortener_domains: Vec<String>,
+    #[serde(default)]
+    pub(crate) shortener_policy: ShortenerPolicy,
+    #[validate(url)]
+    pub(crate) shortener_resolver: Option<String>,
+    #[serde(
+        deserialize_with = "deserializers::duration",
+        default = "defaults::duration_5s"
+    )]
+    pub(crate) shortener_timeout: Duration,
+
     #[validate(length(min = 1))]
     pub(crate) bind: String,
 
@@ -134,28 +283,71 @@ pub struct Vars {
         deserialize_with = "deserializers::duration",
         default = "defaults::duration_3d"
     )]
-    pub(crate) ban_cache_ttl: Duration,
+    pub(crate) url_cache_ttl: Duration,
     #[serde(
         deserialize_with = "deserializers::duration",
         default = "defaults::duration_1d"
     )]
-    pub(crate) ban_cache_idle: Duration,
+    pub(crate) url_cache_idle: Duration,
+
     #[serde(
         deserialize_with = "deserializers::duration",
-        default = "defaults::duration_3d"
+        default = "defaults::duration_1m"
     )]
-    pub(crate) url_cache_ttl: Duration,
+    #[validate(custom(function = validate_interval))]
+    pub(crate) hit_flush_interval: Duration,
+
+    #[serde(
+        deserialize_with = "deserializers::duration",
+        default = "defaults::duration_5m"
+    )]
+    #[validate(custom(function = validate_interval))]
+    pub(crate) expiry_cleanup_interval: Duration,
+
+    #[serde(
+        deserialize_with = "deserializers::duration",
+        default = "defaults::duration_1m"
+    )]
+    #[validate(custom(function = validate_interval))]
+    pub(crate) ban_reload_interval: Duration,
+
+    #[serde(default = "defaults::autoban_strikes")]
+    pub(crate) autoban_strikes: u32,
+    #[serde(
+        deserialize_with = "deserializers::duration",
+        default = "defaults::duration_10m"
+    )]
+    pub(crate) autoban_window: Duration,
     #[serde(
         deserialize_with = "deserializers::duration",
         default = "defaults::duration_1d"
     )]
-    pub(crate) url_cache_idle: Duration,
+    pub(crate) autoban_duration: Duration,
+    #[serde(default = "defaults::autoban_user")]
+    #[validate(length(min = 1))]
+    pub(crate) autoban_user: String,
 
-    #[serde(default = "defaults::max_cache_entries")]
-    pub(crate) ban_cache_max_entries: u64,
     #[serde(default = "defaults::max_cache_entries")]
     pub(crate) url_cache_max_entries: u64,
 
+    #[serde(default)]
+    pub(crate) ip_storage: IpStorageMode,
+    #[serde(default, deserialize_with = "deserializers::ip_hmac_key")]
+    pub(crate) ip_hmac_key: Option<Key>,
+    #[serde(default = "defaults::ip_prefix_v4")]
+    #[validate(range(max = 32))]
+    pub(crate) ip_prefix_v4: u8,
+    #[serde(default = "defaults::ip_prefix_v6")]
+    #[validate(range(max = 128))]
+    pub(crate) ip_prefix_v6: u8,
+    #[serde(default, deserialize_with = "deserializers::optional_duration")]
+    pub(crate) ip_retention: Option<Duration>,
+
+    pub(crate) word_rules_dir: Option<String>,
+
+    pub(crate) geoip_country_db: Option<String>,
+    pub(crate) geoip_asn_db: Option<String>,
+
     // FIXME: encrypt entire session with this, but axum-login isn't ready
     #[serde(
         deserialize_with = "deserializers::csrf_key",
@@ -183,4 +375,40 @@ impl Vars {
         env.validate()?;
         Ok(env)
     }
+
+    // How to lay out generated shady names
+    pub(crate) const fn shady_style(&self) -> Style {
+        Style {
+            directories: self.shady_directories,
+            query: self.shady_query,
+        }
+    }
+
+    // What URL's may be submitted
+    pub(crate) fn url_policy(&self) -> UrlPolicy {
+        UrlPolicy {
+            schemes: self
+                .url_schemes
+                .iter()
+                .map(|s| s.trim().to_ascii_lowercase())
+                .collect(),
+            max_length: self.url_max_length,
+            allow_ip_hosts: self.url_allow_ip_hosts,
+            allow_ports: self.url_allow_ports,
+            allow_credentials: self.url_allow_credentials,
+            allow_private: self.url_allow_private,
+            tld_check: self.url_tld_check,
+            own_hosts: [&self.base_host, &self.shady_host]
+                .iter()
+                .map(|host| strip_port(host).to_ascii_lowercase())
+                .collect(),
+            shortener_domains: self
+                .shortener_domains
+                .iter()
+                .map(|d| d.trim().trim_end_matches('.').to_ascii_lowercase())
+                .filter(|d| !d.is_empty())
+                .collect(),
+            shortener_policy: self.shortener_policy,
+        }
+    }
 }
@@ -17,6 +17,7 @@
 use askama_axum::Template;
 use axum::{
     body::Body,
+    extract::multipart::MultipartError,
     http::StatusCode,
     response::{IntoResponse, Response},
 };
@@ -25,9 +26,13 @@ use tracing::{error, warn};
 use crate::{
     auth::{AuthError, Backend},
     bancache::BanCacheError,
+    bancoalesce::CoalesceError,
+    banexport::ExportError,
     csrf::SessionError,
     urlcache::UrlCacheError,
+    urlfilter::UrlFilterError,
     util::net::{AddressError, NetworkPrefixError},
+    wordcheck::WordCheckError,
 };
 
 // Anything that can go wrong in a handler should go here.
@@ -51,9 +56,18 @@ pub enum AppError {
     #[error(transparent)]
     BanCache(#[from] BanCacheError),
 
+    #[error(transparent)]
+    BanExport(#[from] ExportError),
+
+    #[error(transparent)]
+    BanCoalesce(#[from] CoalesceError),
+
     #[error(transparent)]
     UrlCache(#[from] UrlCacheError),
 
+    #[error(transparent)]
+    UrlFilter(#[from] UrlFilterError),
+
     #[error(transparent)]
     Address(#[from] AddressError),
 
@@ -66,6 +80,15 @@ pub enum AppError {
     #[error(transparent)]
     Regex(#[from] regex::Error),
 
+    #[error(transparent)]
+    WordCheck(#[from] WordCheckError),
+
+    #[error(transparent)]
+    TaskJoin(#[from] tokio::task::JoinError),
+
+    #[error(transparent)]
+    Multipart(#[from] MultipartError),
+
     #[error("Not found")]
     NotFound,
 
@@ -80,6 +103,7 @@ impl IntoResponse for AppError {
                 warn!("CSRF token verification failed");
                 ErrorResponse::bad_request(e.to_string().as_ref())
             }
+            Self::Multipart(e) => ErrorResponse::bad_request(e.body_text().as_ref()),
             Self::UrlValidation(url, error_reason) => {
                 ErrorResponse::url_submission(&url, &error_reason)
             }
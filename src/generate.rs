@@ -12,17 +12,21 @@
  * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
  */
 
-use once_cell::sync::Lazy;
-use rand::{
-    distributions::{DistString, Uniform},
-    prelude::*,
-};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use rand::{distributions::DistString, prelude::*, rngs::ThreadRng};
 use tokio::task::spawn_blocking;
 
-use crate::util::{macros::arr, string::WebsafeAlphabet};
+use crate::{
+    util::{macros::arr, string::WebsafeAlphabet},
+    web::is_reserved_path,
+};
 
 // The thing that generates shady URL's
 
+// Longest path we'll generate, so it fits in the database everywhere
+const MAX_PATH_LEN: usize = 255;
+
 // A mangler type
 #[derive(PartialEq, Eq, Copy, Clone)]
 enum Mangler {
@@ -34,27 +38,127 @@ enum Mangler {
     HeckTransform,
 }
 
-pub struct Generator;
+// How shady names are laid out
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Style {
+    // Put the filename inside of fake directories
+    pub directories: bool,
+    // Follow the name with a fake query string
+    pub query: bool,
+}
+
+// A generated shady name
+pub struct ShadyName {
+    // The part we store and look up, which may contain slashes
+    pub path: String,
+    // Purely decorative; it's never stored, and ignored when following the link
+    pub query: Option<String>,
+}
+
+impl Display for ShadyName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match &self.query {
+            Some(query) => write!(f, "{}?{query}", self.path),
+            None => write!(f, "{}", self.path),
+        }
+    }
+}
+
+pub struct Generator<R: Rng> {
+    rng: R,
+    style: Style,
+    // Information content (in bits) of every choice made for the last name.
+    // Averaged over many names, this estimates the entropy of the generator.
+    info_bits: f64,
+}
+
+impl Generator<ThreadRng> {
+    // async wrapper around generate_shady_name
+    pub(crate) async fn shady_name(style: Style) -> ShadyName {
+        spawn_blocking(move || Self::new(thread_rng(), style).generate_shady_name())
+            .await
+            .expect("shady_name task unexpectedly failed")
+    }
+}
+
+impl<R: Rng> Generator<R> {
+    pub(crate) const fn new(rng: R, style: Style) -> Self {
+        Self {
+            rng,
+            style,
+            info_bits: 0.0,
+        }
+    }
+
+    // Information content of the path of the last generated name, in bits.
+    // The query string isn't counted, as it plays no part in telling links apart.
+    pub(crate) const fn info_bits(&self) -> f64 {
+        self.info_bits
+    }
+
+    // Account for an outcome that happened with probability p
+    fn note(&mut self, p: f64) {
+        self.info_bits -= p.log2();
+    }
+
+    // Pick a number in [low, high)
+    fn pick(&mut self, low: u32, high: u32) -> u32 {
+        self.note(1.0 / f64::from(high - low));
+        self.rng.gen_range(low..high)
+    }
+
+    // Pick one item from a list
+    fn pick_from<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        // Our lists are nowhere near u32::MAX long
+        #[allow(clippy::cast_possible_truncation)]
+        let i = self.pick(0, items.len() as u32);
+        items[i as usize]
+    }
+
+    // Pick count unique items from a list
+    fn pick_many<'a>(&mut self, items: &[&'a str], count: u32) -> Vec<&'a str> {
+        // The order isn't guaranteed to be random, so only count the combinations
+        #[allow(clippy::cast_possible_truncation)]
+        let len = items.len() as u32;
+        for i in 0..count {
+            self.note(f64::from(i + 1) / f64::from(len - i));
+        }
+
+        items
+            .choose_multiple(&mut self.rng, count as usize)
+            .copied()
+            .collect()
+    }
+
+    // Returns true with a 1/n chance
+    fn one_in(&mut self, n: u32) -> bool {
+        let hit = self.rng.gen_range(0..n) == 0;
+        let p = 1.0 / f64::from(n);
+        self.note(if hit { p } else { 1.0 - p });
+        hit
+    }
+
+    fn coin(&mut self) -> bool {
+        self.note(0.5);
+        self.rng.gen()
+    }
 
-impl Generator {
     // Generate the random looking part of the URL
     // This adds some more randomness to the process, but otherwise does nothing
-    fn generate_hash() -> String {
-        let distr_len = Lazy::new(|| Uniform::new(5, 9));
-        let mut rng = thread_rng();
-        let len = (*distr_len).sample(&mut rng);
-        WebsafeAlphabet.sample_string(&mut rng, len)
+    fn generate_hash(&mut self) -> String {
+        let len = self.pick(5, 9);
+        self.info_bits += f64::from(len) * f64::from(WebsafeAlphabet::SIZE).log2();
+        WebsafeAlphabet.sample_string(&mut self.rng, len as usize)
     }
 
     // Given a fragment of the shady URL, mangle it with the given mangler.
-    fn perform_mangle(mangler: Mangler, fragment: &str) -> String {
-        let mut rng = thread_rng();
+    // Characters the mangler can't change don't consume any randomness.
+    fn perform_mangle(&mut self, mangler: Mangler, fragment: &str) -> String {
         match mangler {
             Mangler::RandomUppercase => fragment
                 .chars()
                 .map(|ch| {
-                    let distr_cap = Lazy::new(|| Uniform::new(0, 3));
-                    if (*distr_cap).sample(&mut rng) == 0 {
+                    if ch.is_lowercase() && self.one_in(3) {
                         ch.to_uppercase().collect()
                     } else {
                         ch.to_string()
@@ -65,10 +169,9 @@ impl Generator {
             Mangler::ReplaceSeps => fragment
                 .chars()
                 .map(|ch| {
-                    let distr_replace = Lazy::new(|| Uniform::new(0, 4));
-                    if ch == '-' && (*distr_replace).sample(&mut rng) == 0 {
+                    if ch == '-' && self.one_in(4) {
                         arr!(const SEPS: [&str; _] = ["!", "_", "+", "$"]);
-                        (*SEPS.choose(&mut rng).unwrap()).to_string()
+                        self.pick_from(&SEPS).to_string()
                     } else {
                         ch.to_string()
                     }
@@ -77,18 +180,19 @@ impl Generator {
             Mangler::NumberLookalike => fragment
                 .chars()
                 .map(|ch| {
-                    let distr_replace = Lazy::new(|| Uniform::new(0, 4));
-                    if (*distr_replace).sample(&mut rng) == 0 {
-                        match ch {
-                            'o' | 'O' => '0',
-                            'a' | 'A' => '4',
-                            'e' | 'E' => '3',
-                            'g' | 'G' => '9',
-                            'i' | 'I' | 'l' | 'L' => '1',
-                            's' | 'S' => '5',
-                            't' | 'T' => '7',
-                            _ => ch,
-                        }
+                    let lookalike = match ch {
+                        'o' | 'O' => '0',
+                        'a' | 'A' => '4',
+                        'e' | 'E' => '3',
+                        'g' | 'G' => '9',
+                        'i' | 'I' | 'l' | 'L' => '1',
+                        's' | 'S' => '5',
+                        't' | 'T' => '7',
+                        _ => return ch,
+                    };
+
+                    if self.one_in(4) {
+                        lookalike
                     } else {
                         ch
                     }
@@ -96,8 +200,7 @@ impl Generator {
                 .collect(),
             Mangler::HeckTransform => {
                 // TODO: better way to do this?
-                let distr_transform = Lazy::new(|| Uniform::new(0, 6));
-                match (*distr_transform).sample(&mut rng) {
+                match self.pick(0, 6) {
                     0 => heck::AsLowerCamelCase(fragment).to_string(),
                     1 => heck::AsUpperCamelCase(fragment).to_string(),
                     2 => heck::AsShoutyKebabCase(fragment).to_string(),
@@ -112,10 +215,8 @@ impl Generator {
     }
 
     // Choose a random mangler
-    fn get_mangler() -> Mangler {
-        let mut rng = thread_rng();
-        let distr_mangle = Lazy::new(|| Uniform::new(0, 15));
-        match (*distr_mangle).sample(&mut rng) {
+    fn get_mangler(&mut self) -> Mangler {
+        let mangler = match self.rng.gen_range(0..15) {
             // 1/3 probability of selecting a mangler
             0 => Mangler::AllUppercase,
             1 => Mangler::RandomUppercase,
@@ -123,69 +224,133 @@ impl Generator {
             3 => Mangler::NumberLookalike,
             4 => Mangler::HeckTransform,
             _ => Mangler::NoOp,
-        }
+        };
+        self.note(if mangler == Mangler::NoOp {
+            10.0 / 15.0
+        } else {
+            1.0 / 15.0
+        });
+        mangler
     }
 
     // Mangle a fragment passed in.
-    fn mangle_fragment(fragment: &str) -> String {
+    fn mangle_fragment(&mut self, fragment: &str) -> String {
         // Select mangling function
-        let mut rng = thread_rng();
-        let distr_second_mangler = Lazy::new(|| Uniform::new(0, 4));
-        let mangler = Self::get_mangler();
-        let new = Self::perform_mangle(mangler, fragment);
+        let mangler = self.get_mangler();
+        let new = self.perform_mangle(mangler, fragment);
 
-        if (*distr_second_mangler).sample(&mut rng) == 0 {
+        if mangler != Mangler::NoOp && self.one_in(4) {
             // 1/4 chance to apply a second mangler
             let mangler = match mangler {
                 Mangler::AllUppercase | Mangler::RandomUppercase | Mangler::HeckTransform => {
                     // Don't repeat a case mangling or heck transform
-                    if rng.gen() {
+                    if self.coin() {
                         Mangler::ReplaceSeps
                     } else {
                         Mangler::NumberLookalike
                     }
                 }
-                Mangler::ReplaceSeps => match rng.gen_range(0..3) {
+                Mangler::ReplaceSeps => match self.pick(0, 3) {
                     0 => Mangler::AllUppercase,
                     1 => Mangler::RandomUppercase,
                     2 => Mangler::NumberLookalike,
                     _ => unreachable!(),
                 },
-                Mangler::NumberLookalike => match rng.gen_range(0..3) {
+                Mangler::NumberLookalike => match self.pick(0, 3) {
                     0 => Mangler::AllUppercase,
                     1 => Mangler::RandomUppercase,
                     2 => Mangler::ReplaceSeps,
                     _ => unreachable!(),
                 },
-                Mangler::NoOp => Mangler::NoOp,
+                Mangler::NoOp => unreachable!(),
             };
 
-            return Self::perform_mangle(mangler, &new);
+            return self.perform_mangle(mangler, &new);
         }
 
         new
     }
 
-    // Create a shady-looking filename for the URL
-    fn generate_shady_filename() -> String {
-        arr!(const SEPS: [&str; _] = ["!", "_", "+", "~"]);
+    // Create a shady-looking name for the URL, which no other route will shadow
+    pub(crate) fn generate_shady_name(&mut self) -> ShadyName {
+        let path = loop {
+            let path = self.generate_path();
+            if path.len() <= MAX_PATH_LEN && !is_reserved_path(&path) {
+                break path;
+            }
+        };
+
+        let query = if self.style.query {
+            let info_bits = self.info_bits;
+            let query = self.generate_query();
+            self.info_bits = info_bits;
+            Some(query)
+        } else {
+            None
+        };
+
+        ShadyName { path, query }
+    }
+
+    // Create the stored part of the name, without checking it can be reached
+    fn generate_path(&mut self) -> String {
+        self.info_bits = 0.0;
 
-        let mut rng = thread_rng();
+        let mut path = String::new();
+        if self.style.directories {
+            for _ in 0..self.pick(1, 4) {
+                let dir = if self.one_in(3) {
+                    let fragment = self.pick_from(&self::strings::NSFW);
+                    self.mangle_fragment(fragment)
+                } else {
+                    self.pick_from(&self::strings::DIRS).to_string()
+                };
+                path.push_str(&dir);
+                path.push('/');
+            }
+        }
+
+        path.push_str(&self.generate_filename());
+        path
+    }
+
+    // Create a fake query string, like ?utm_source=pr0n&token=AbC123
+    fn generate_query(&mut self) -> String {
+        let count = self.pick(1, 5);
+        let keys = self.pick_many(&self::strings::QUERY_KEYS, count);
+        let params: Vec<_> = keys
+            .into_iter()
+            .map(|key| {
+                let value = if self.coin() {
+                    let fragment = self.pick_from(&self::strings::NSFW);
+                    self.mangle_fragment(fragment)
+                } else {
+                    self.generate_hash()
+                };
+                format!("{key}={value}")
+            })
+            .collect();
+
+        params.join("&")
+    }
 
-        // These never change, so no point in regenerating them each time
-        let distr_count = Lazy::new(|| Uniform::new_inclusive(4, 7));
+    // Create a shady-looking filename
+    fn generate_filename(&mut self) -> String {
+        arr!(const SEPS: [&str; _] = ["!", "_", "+", "~"]);
 
-        let token_count = distr_count.sample(&mut rng);
-        let mut nsfw_str_count = token_count;
+        let token_count = self.pick(4, 8);
+        // The hash always takes one of the tokens
+        let mut nsfw_str_count = token_count - 1;
 
-        let hash = Self::generate_hash();
-        let hash_pos = rng.gen_range(1..token_count);
+        let hash = self.generate_hash();
+        let hash_pos = self.pick(1, token_count);
 
-        let fake_extension_pos = if rng.gen() {
+        let fake_extension_pos = if self.coin() {
+            nsfw_str_count -= 1;
+            self.note(1.0 / f64::from(token_count - 2));
             loop {
-                let n = rng.gen_range(1..token_count);
+                let n = self.rng.gen_range(1..token_count);
                 if n != hash_pos {
-                    nsfw_str_count -= 1;
                     break n;
                 }
             }
@@ -195,25 +360,24 @@ impl Generator {
         };
 
         // Gather unique strings up front
-        let mut nsfw_strs: Vec<_> = self::strings::NSFW
-            .choose_multiple(&mut rng, nsfw_str_count)
-            .map(|s| Self::mangle_fragment(s))
+        let mut nsfw_strs: Vec<_> = self
+            .pick_many(&self::strings::NSFW, nsfw_str_count)
+            .into_iter()
+            .map(|s| self.mangle_fragment(s))
             .collect();
 
         // nsfw strings + extension
-        let mut out = Vec::with_capacity(token_count);
+        let mut out = Vec::with_capacity(token_count as usize);
         for i in 0..token_count {
             if i > 0 && i != fake_extension_pos {
                 // Prepend
-                // SAFETY: never fails
-                out.push(unsafe { (*SEPS.choose(&mut rng).unwrap_unchecked()).to_string() });
+                out.push(self.pick_from(&SEPS).to_string());
             }
 
             let push_val = if i == hash_pos {
                 hash.clone()
             } else if i == fake_extension_pos {
-                // SAFETY: never fails
-                unsafe { (*self::strings::EXT.choose(&mut rng).unwrap_unchecked()).to_string() }
+                self.pick_from(&self::strings::EXT).to_string()
             } else {
                 // SAFETY: nsfw_strs always has enough strings
                 unsafe { nsfw_strs.pop().unwrap_unchecked() }
@@ -221,30 +385,20 @@ impl Generator {
             out.push(push_val);
         }
 
-        if rng.gen() || fake_extension_pos <= token_count {
+        if fake_extension_pos <= token_count || self.coin() {
             // Add extension
-            // SAFETY: never fails
-            out.push(unsafe {
-                (*self::strings::EXT_EXE.choose(&mut rng).unwrap_unchecked()).to_string()
-            });
+            out.push(self.pick_from(&self::strings::EXT_EXE).to_string());
         }
 
         out.into_iter().collect()
     }
-
-    // async wrapper around generate_shady_filename
-    pub(crate) async fn shady_filename() -> String {
-        spawn_blocking(Self::generate_shady_filename)
-            .await
-            .expect("shady_filename task unexpectedly failed")
-    }
 }
 
-mod strings {
+pub mod strings {
     use super::arr;
 
     // NSFW fragments to use in the string
-    arr!(pub(super) const NSFW: [&str; _] = [
+    arr!(pub const NSFW: [&str; _] = [
         "---click-here---",
         "---install-virus---",
         "0percentartificial",
@@ -1241,7 +1395,7 @@ mod strings {
     ]);
 
     // Various extensions to use in a shady filename
-    arr!(pub(super) const EXT: [&str; _] = [
+    arr!(pub const EXT: [&str; _] = [
         ".avi", ".bas", ".bz2", ".csv", ".divx", ".dll", ".doc", ".docx", ".flv", ".gif", ".gz", ".htm",
         ".html", ".img", ".ini", ".jar", ".js", ".jpeg", ".jpg", ".lzma", ".m1v", ".m4a", ".mid",
         ".midi", ".mkv", ".mod", ".mov", ".movie", ".mpa", ".mpe", ".mpeg", ".mpg", ".mp3", ".mp4",
@@ -1250,8 +1404,25 @@ mod strings {
         ".txt", ".webm", ".webp", ".vbs", ".xaf", ".xhtml", ".xls", ".xlsx", ".xml", ".xz", ".zip",
     ]);
 
+    // Fake directories to put the filename in
+    arr!(pub const DIRS: [&str; _] = [
+        "0day", "backup", "bin", "cdn", "cgi-bin", "cracked", "db", "dl", "downloads", "dump",
+        "files", "free", "full-version", "hidden", "incoming", "install", "iso", "keygen",
+        "leaked", "members", "mirror", "new", "nulled", "old", "passwords", "patched", "payload",
+        "premium", "private", "pub", "rips", "scene", "secret", "serials", "setup", "temp", "tmp",
+        "torrents", "unlocked", "uploads", "vip", "warez", "wp-content", "wp-includes", "xxx",
+    ]);
+
+    // Keys for fake query strings
+    arr!(pub const QUERY_KEYS: [&str; _] = [
+        "aff_id", "affiliate", "auth", "bypass", "click_id", "confirm", "coupon", "download_id",
+        "key", "promo", "redirect", "ref", "safe", "session", "sessionid", "sid", "token",
+        "tracking", "uac", "uid", "utm_campaign", "utm_content", "utm_medium", "utm_source",
+        "verify",
+    ]);
+
     // Executable formats, to make it look really shady
-    arr!(pub(super) const EXT_EXE: [&str; _] = [
+    arr!(pub const EXT_EXE: [&str; _] = [
         ".app", ".bat", ".dmg", ".exe", ".msi", ".run", ".script",
     ]);
 }
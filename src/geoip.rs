@@ -0,0 +1,287 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/geoip.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// Look up where IP's are, for banning by country or autonomous system.
+//
+// The databases are MaxMind-format files, read into memory. They can be swapped out underneath us
+// (geoipupdate does exactly that), so we keep their modification times and reopen them when they
+// change.
+
+use std::{
+    fmt,
+    fs::metadata,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+use time::OffsetDateTime;
+use tokio::{sync::RwLock, task::spawn_blocking};
+use tracing::{info, trace, warn};
+
+use entity::sea_orm_active_enums::GeoKind;
+
+use crate::env::Vars;
+
+// Every kind of GeoIP ban, in the order we show them
+pub const KINDS: [GeoKind; 2] = [GeoKind::Country, GeoKind::Asn];
+
+#[derive(Debug, thiserror::Error)]
+pub enum GeoIpError {
+    #[error("Could not open GeoIP database {}: {}", .0.display(), .1)]
+    Open(PathBuf, MaxMindDBError),
+
+    #[error(transparent)]
+    TaskJoin(#[from] tokio::task::JoinError),
+
+    #[error("Invalid country code: {}", .0)]
+    Country(String),
+
+    #[error("Invalid AS number: {}", .0)]
+    Asn(String),
+}
+
+// Human-readable name of a GeoIP ban kind
+pub const fn describe(kind: GeoKind) -> &'static str {
+    match kind {
+        GeoKind::Country => "Country",
+        GeoKind::Asn => "Autonomous system",
+    }
+}
+
+// Check a ban value is valid for its kind, and put it in the form we store: upper-case ISO country
+// codes, and AS numbers without the "AS"
+pub fn normalize(kind: GeoKind, value: &str) -> Result<String, GeoIpError> {
+    let value = value.trim();
+    match kind {
+        GeoKind::Country => {
+            if value.len() == 2 && value.bytes().all(|b| b.is_ascii_alphabetic()) {
+                Ok(value.to_ascii_uppercase())
+            } else {
+                Err(GeoIpError::Country(value.to_string()))
+            }
+        }
+        GeoKind::Asn => {
+            let number = value
+                .strip_prefix("AS")
+                .or_else(|| value.strip_prefix("as"))
+                .unwrap_or(value);
+            number
+                .parse::<u32>()
+                .map(|asn| asn.to_string())
+                .map_err(|_| GeoIpError::Asn(value.to_string()))
+        }
+    }
+}
+
+// A ban value fit for display
+pub fn display(kind: GeoKind, value: &str) -> String {
+    match kind {
+        GeoKind::Country => value.to_string(),
+        GeoKind::Asn => format!("AS{value}"),
+    }
+}
+
+// What we know about where an IP is
+#[derive(Clone, Debug, Default)]
+pub struct Location {
+    pub(crate) country: Option<String>,
+    pub(crate) asn: Option<u32>,
+    pub(crate) organization: Option<String>,
+}
+
+// An open database, and the file it came from
+struct Database {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    reader: Reader<Vec<u8>>,
+}
+
+impl Database {
+    async fn open(path: &Path) -> Result<Self, GeoIpError> {
+        let path = path.to_path_buf();
+        spawn_blocking(move || {
+            let modified = metadata(&path).and_then(|m| m.modified()).ok();
+            let reader =
+                Reader::open_readfile(&path).map_err(|e| GeoIpError::Open(path.clone(), e))?;
+            Ok(Self {
+                path,
+                modified,
+                reader,
+            })
+        })
+        .await?
+    }
+
+    // The kind of database and when it was built
+    fn describe(&self) -> String {
+        let meta = &self.reader.metadata;
+        let built = i64::try_from(meta.build_epoch)
+            .ok()
+            .and_then(|epoch| OffsetDateTime::from_unix_timestamp(epoch).ok())
+            .map_or_else(|| "unknown".to_string(), |built| built.date().to_string());
+        format!(
+            "{} ({}, built {built})",
+            self.path.display(),
+            meta.database_type
+        )
+    }
+}
+
+#[derive(Default)]
+struct Databases {
+    country: Option<Database>,
+    asn: Option<Database>,
+}
+
+#[derive(Clone)]
+pub struct GeoIp {
+    country_path: Option<PathBuf>,
+    asn_path: Option<PathBuf>,
+    databases: Arc<RwLock<Databases>>,
+}
+
+// Readers don't implement Debug, so leave the databases out
+impl fmt::Debug for GeoIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GeoIp")
+            .field("country_path", &self.country_path)
+            .field("asn_path", &self.asn_path)
+            .finish_non_exhaustive()
+    }
+}
+
+// True if a file has been replaced since it was last modified at the given time.
+// This blocks, so keep it off the async runtime.
+fn has_changed(path: &Path, modified: Option<SystemTime>) -> bool {
+    metadata(path)
+        .and_then(|m| m.modified())
+        .is_ok_and(|now| Some(now) != modified)
+}
+
+// Open a database if there's one configured
+async fn open(path: Option<&PathBuf>) -> Result<Option<Database>, GeoIpError> {
+    match path {
+        Some(path) => Ok(Some(Database::open(path).await?)),
+        None => Ok(None),
+    }
+}
+
+impl GeoIp {
+    // Open whichever databases are configured
+    pub(crate) async fn new(env: &Vars) -> Result<Self, GeoIpError> {
+        let geoip = Self {
+            country_path: env.geoip_country_db.as_ref().map(PathBuf::from),
+            asn_path: env.geoip_asn_db.as_ref().map(PathBuf::from),
+            databases: Arc::default(),
+        };
+        geoip.reload().await?;
+        Ok(geoip)
+    }
+
+    // True if there are any databases to look things up in
+    pub(crate) const fn is_enabled(&self) -> bool {
+        self.country_path.is_some() || self.asn_path.is_some()
+    }
+
+    // Reopen every database.
+    // If any of them fail to open, the ones already open are kept.
+    pub(crate) async fn reload(&self) -> Result<(), GeoIpError> {
+        let country = open(self.country_path.as_ref()).await?;
+        let asn = open(self.asn_path.as_ref()).await?;
+
+        for database in [&country, &asn].into_iter().flatten() {
+            info!("Loaded GeoIP database {}", database.describe());
+        }
+        *self.databases.write().await = Databases { country, asn };
+        Ok(())
+    }
+
+    // Reopen any databases whose files have changed
+    pub(crate) async fn reload_changed(&self) -> Result<(), GeoIpError> {
+        let opened: Vec<(PathBuf, Option<SystemTime>)> = {
+            let databases = self.databases.read().await;
+            [&databases.country, &databases.asn]
+                .into_iter()
+                .flatten()
+                .map(|database| (database.path.clone(), database.modified))
+                .collect()
+        };
+
+        let changed = spawn_blocking(move || {
+            opened
+                .iter()
+                .any(|(path, modified)| has_changed(path, *modified))
+        })
+        .await?;
+        if changed {
+            self.reload().await?;
+        }
+        Ok(())
+    }
+
+    // Describe each database that's open, for the admin pages
+    pub(crate) async fn describe(&self) -> Vec<(GeoKind, String)> {
+        let databases = self.databases.read().await;
+        [
+            (GeoKind::Country, &databases.country),
+            (GeoKind::Asn, &databases.asn),
+        ]
+        .into_iter()
+        .filter_map(|(kind, database)| database.as_ref().map(|d| (kind, d.describe())))
+        .collect()
+    }
+
+    // Find out where an IP is.
+    // Addresses the databases don't cover come back empty; that's not an error.
+    pub(crate) async fn lookup(&self, ip: IpAddr) -> Location {
+        // Mapped addresses are stored as IPv4, so they have to be looked up that way
+        let ip = ip.to_canonical();
+        let databases = self.databases.read().await;
+        let mut location = Location::default();
+
+        if let Some(database) = &databases.country {
+            match database.reader.lookup::<geoip2::Country>(ip) {
+                Ok(found) => {
+                    // Fall back on where the network is registered if we don't know better
+                    location.country = found
+                        .country
+                        .and_then(|c| c.iso_code)
+                        .or_else(|| found.registered_country.and_then(|c| c.iso_code))
+                        .map(str::to_string);
+                }
+                Err(MaxMindDBError::AddressNotFoundError(_)) => (),
+                Err(e) => warn!("GeoIP country lookup for {ip} failed: {e}"),
+            }
+        }
+
+        if let Some(database) = &databases.asn {
+            match database.reader.lookup::<geoip2::Asn>(ip) {
+                Ok(found) => {
+                    location.asn = found.autonomous_system_number;
+                    location.organization =
+                        found.autonomous_system_organization.map(str::to_string);
+                }
+                Err(MaxMindDBError::AddressNotFoundError(_)) => (),
+                Err(e) => warn!("GeoIP ASN lookup for {ip} failed: {e}"),
+            }
+        }
+        drop(databases);
+
+        trace!("{ip}: {location:?}");
+        location
+    }
+}
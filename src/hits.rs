@@ -0,0 +1,144 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/hits.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// Hit counting for URL filters, CIDR bans, and GeoIP bans.
+//
+// Hits are collected in memory and written out in batches by a periodic job (see jobs.rs), so
+// blocking a submission never has to wait on the database. Whatever's left is written out when the
+// server shuts down.
+
+use std::{
+    collections::HashMap,
+    mem::take,
+    sync::{Arc, Mutex},
+};
+
+use sea_orm::{DbConn, DbErr};
+use time::OffsetDateTime;
+use tracing::trace;
+
+use service::Mutation;
+
+// Hits not yet written to the database
+#[derive(Clone, Copy, Debug)]
+struct Hits {
+    count: i64,
+    last_hit_at: OffsetDateTime,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    url_filters: HashMap<i64, Hits>,
+    cidr_bans: HashMap<i64, Hits>,
+    geo_bans: HashMap<i64, Hits>,
+}
+
+fn record(hits: &mut HashMap<i64, Hits>, ids: &[i64], now: OffsetDateTime) {
+    for id in ids {
+        hits.entry(*id)
+            .and_modify(|hits| {
+                hits.count += 1;
+                hits.last_hit_at = now;
+            })
+            .or_insert(Hits {
+                count: 1,
+                last_hit_at: now,
+            });
+    }
+}
+
+// Put hits that couldn't be written back with the ones recorded since
+fn restore(hits: &mut HashMap<i64, Hits>, unwritten: HashMap<i64, Hits>) {
+    for (id, unwritten) in unwritten {
+        hits.entry(id)
+            .and_modify(|hits| {
+                hits.count += unwritten.count;
+                hits.last_hit_at = hits.last_hit_at.max(unwritten.last_hit_at);
+            })
+            .or_insert(unwritten);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct HitCounter {
+    pending: Arc<Mutex<Pending>>,
+}
+
+impl HitCounter {
+    // Record a hit on each of the given URL filters
+    pub(crate) fn url_filter_hits(&self, ids: &[i64]) {
+        let now = OffsetDateTime::now_utc();
+        let mut pending = self.pending.lock().expect("Hit counter lock poisoned");
+        record(&mut pending.url_filters, ids, now);
+    }
+
+    // Record a hit on each of the given CIDR bans
+    pub(crate) fn cidr_ban_hits(&self, ids: &[i64]) {
+        let now = OffsetDateTime::now_utc();
+        let mut pending = self.pending.lock().expect("Hit counter lock poisoned");
+        record(&mut pending.cidr_bans, ids, now);
+    }
+
+    // Record a hit on each of the given GeoIP bans
+    pub(crate) fn geo_ban_hits(&self, ids: &[i64]) {
+        let now = OffsetDateTime::now_utc();
+        let mut pending = self.pending.lock().expect("Hit counter lock poisoned");
+        record(&mut pending.geo_bans, ids, now);
+    }
+
+    // Write all pending hits to the database.
+    // Rows deleted in the meantime are skipped, as the updates don't match anything.
+    // If writing fails, whatever wasn't written is kept for next time.
+    pub(crate) async fn flush(&self, db: &DbConn) -> Result<(), DbErr> {
+        let mut unwritten = take(&mut *self.pending.lock().expect("Hit counter lock poisoned"));
+        let result = Self::write(db, &mut unwritten).await;
+
+        if result.is_err() {
+            let mut pending = self.pending.lock().expect("Hit counter lock poisoned");
+            restore(&mut pending.url_filters, unwritten.url_filters);
+            restore(&mut pending.cidr_bans, unwritten.cidr_bans);
+            restore(&mut pending.geo_bans, unwritten.geo_bans);
+        }
+        result
+    }
+
+    // Write hits to the database, taking out each one as it's written
+    async fn write(db: &DbConn, pending: &mut Pending) -> Result<(), DbErr> {
+        let ids: Vec<i64> = pending.url_filters.keys().copied().collect();
+        for id in ids {
+            let hits = pending.url_filters[&id];
+            trace!("Flushing {} hits for URL filter {id}", hits.count);
+            Mutation::add_url_filter_hits(db, id, hits.count, hits.last_hit_at).await?;
+            pending.url_filters.remove(&id);
+        }
+
+        let ids: Vec<i64> = pending.cidr_bans.keys().copied().collect();
+        for id in ids {
+            let hits = pending.cidr_bans[&id];
+            trace!("Flushing {} hits for CIDR ban {id}", hits.count);
+            Mutation::add_cidr_ban_hits(db, id, hits.count, hits.last_hit_at).await?;
+            pending.cidr_bans.remove(&id);
+        }
+
+        let ids: Vec<i64> = pending.geo_bans.keys().copied().collect();
+        for id in ids {
+            let hits = pending.geo_bans[&id];
+            trace!("Flushing {} hits for GeoIP ban {id}", hits.count);
+            Mutation::add_geo_ban_hits(db, id, hits.count, hits.last_hit_at).await?;
+            pending.geo_bans.remove(&id);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/ipstorage.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// How much of a submitter's IP we keep.
+//
+// Instead of the whole address, we can keep a keyed hash of it, which is enough to find everything
+// someone submitted but can't be turned back into an address without the key, and/or the address
+// with the host part zeroed, which is enough to ban the network it came from.
+
+use std::{
+    fmt::Write,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use hmac::{Hmac, Mac};
+use ipnetwork::IpNetwork;
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::{
+    env::{Key, Vars},
+    util::net::ipaddr_to_int,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// What to store for a submitter's IP
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IpStorageMode {
+    // The whole address
+    #[default]
+    Plain,
+    // A keyed hash of the address
+    Hmac,
+    // The address truncated to its network prefix
+    Prefix,
+    // Both the hash and the truncated address
+    HmacPrefix,
+}
+
+impl IpStorageMode {
+    const fn uses_hmac(self) -> bool {
+        matches!(self, Self::Hmac | Self::HmacPrefix)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IpStorageError {
+    #[error("IP_HMAC_KEY must be set when IP_STORAGE is {0:?}")]
+    MissingKey(IpStorageMode),
+}
+
+// What gets stored for a submitter
+#[derive(Debug)]
+pub struct StoredIp {
+    pub(crate) ip: Option<IpAddr>,
+    pub(crate) ip_hmac: Option<String>,
+}
+
+// What links submitted from an IP could have been stored under
+#[derive(Debug)]
+pub struct Submitter {
+    // Only the IP itself
+    pub(crate) ips: Vec<IpAddr>,
+    pub(crate) ip_hmac: Option<String>,
+    // The network truncated addresses are shared by, when they're stored; links stored under it
+    // could have come from anyone there
+    pub(crate) network: Option<IpNetwork>,
+}
+
+#[derive(Clone)]
+pub struct IpStorage {
+    mode: IpStorageMode,
+    key: Option<Key>,
+    prefix_v4: u8,
+    prefix_v6: u8,
+}
+
+impl IpStorage {
+    pub(crate) const fn new(env: &Vars) -> Result<Self, IpStorageError> {
+        if env.ip_storage.uses_hmac() && env.ip_hmac_key.is_none() {
+            return Err(IpStorageError::MissingKey(env.ip_storage));
+        }
+
+        Ok(Self {
+            mode: env.ip_storage,
+            key: env.ip_hmac_key,
+            prefix_v4: env.ip_prefix_v4,
+            prefix_v6: env.ip_prefix_v6,
+        })
+    }
+
+    // Keyed hash of an address, if we have a key.
+    // Addresses are hashed in their stored range form, so IPv4 and mapped IPv4 hash the same.
+    fn hmac(&self, addr: IpAddr) -> Option<String> {
+        let key = self.key.as_ref()?;
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes keys of any size");
+        mac.update(&ipaddr_to_int(addr.to_canonical()).to_be_bytes());
+        Some(
+            mac.finalize()
+                .into_bytes()
+                .iter()
+                .fold(String::with_capacity(64), |mut hex, byte| {
+                    let _ = write!(hex, "{byte:02x}");
+                    hex
+                }),
+        )
+    }
+
+    // An address with everything past the configured prefix zeroed
+    fn truncate(&self, addr: IpAddr) -> IpAddr {
+        match addr.to_canonical() {
+            IpAddr::V4(addr) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix_v4))
+                    .unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(addr) & mask))
+            }
+            IpAddr::V6(addr) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix_v6))
+                    .unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(addr) & mask))
+            }
+        }
+    }
+
+    // What to store for a submitter's IP
+    pub(crate) fn store(&self, addr: IpAddr) -> StoredIp {
+        match self.mode {
+            IpStorageMode::Plain => StoredIp {
+                ip: Some(addr),
+                ip_hmac: None,
+            },
+            IpStorageMode::Hmac => StoredIp {
+                ip: None,
+                ip_hmac: self.hmac(addr),
+            },
+            IpStorageMode::Prefix => StoredIp {
+                ip: Some(self.truncate(addr)),
+                ip_hmac: None,
+            },
+            IpStorageMode::HmacPrefix => StoredIp {
+                ip: Some(self.truncate(addr)),
+                ip_hmac: self.hmac(addr),
+            },
+        }
+    }
+
+    // What links submitted from an IP could have been stored under.
+    // Links stored before the mode changed are found too, as long as the key hasn't changed.
+    // A truncated address is shared by its whole network, so it's kept apart from the IP itself.
+    pub(crate) fn submitter(&self, addr: IpAddr) -> Submitter {
+        let network = if matches!(self.mode, IpStorageMode::Prefix | IpStorageMode::HmacPrefix) {
+            let truncated = self.truncate(addr);
+            let prefix = if truncated.is_ipv4() {
+                self.prefix_v4
+            } else {
+                self.prefix_v6
+            };
+            // Prefixes are checked when they're loaded
+            IpNetwork::new(truncated, prefix).ok()
+        } else {
+            None
+        };
+
+        Submitter {
+            ips: vec![addr],
+            ip_hmac: self.hmac(addr),
+            network,
+        }
+    }
+}
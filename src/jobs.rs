@@ -0,0 +1,144 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/jobs.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// Periodic background jobs
+
+use std::future::Future;
+
+use sea_orm::DbErr;
+use time::{Duration, OffsetDateTime};
+use tokio::{task::JoinHandle, time::interval};
+use tracing::{error, info, trace};
+
+use service::Mutation;
+
+use crate::{bancache::BanCacheError, geoip::GeoIpError, state::AppState};
+
+#[derive(Debug, thiserror::Error)]
+pub enum JobError {
+    #[error(transparent)]
+    Db(#[from] DbErr),
+
+    #[error(transparent)]
+    BanCache(#[from] BanCacheError),
+
+    #[error(transparent)]
+    GeoIp(#[from] GeoIpError),
+}
+
+// Run a job every period, starting one period from now.
+// Errors are logged, and the job is tried again next time around.
+fn every<F, Fut>(name: &'static str, period: Duration, state: AppState, job: F) -> JoinHandle<()>
+where
+    F: Fn(AppState) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), JobError>> + Send,
+{
+    tokio::spawn(async move {
+        let mut ticker = interval(period.unsigned_abs());
+        // The first tick completes immediately
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            trace!("Running job {name}");
+            if let Err(e) = job(state.clone()).await {
+                error!("Job {name} failed: {e}");
+            }
+        }
+    })
+}
+
+// Write out hit counts for filters and bans
+async fn flush_hits(state: AppState) -> Result<(), JobError> {
+    state.hits.flush(&state.db).await?;
+    Ok(())
+}
+
+// Delete bans and URL filters that have expired.
+// Both caches drop expired entries on their own, so they don't need invalidating.
+async fn expire(state: AppState) -> Result<(), JobError> {
+    let now = OffsetDateTime::now_utc();
+
+    let result = Mutation::delete_expired_cidr_bans(&state.db, now).await?;
+    if result.rows_affected > 0 {
+        info!("Deleted {} expired CIDR bans", result.rows_affected);
+    }
+
+    let result = Mutation::delete_expired_geo_bans(&state.db, now).await?;
+    if result.rows_affected > 0 {
+        info!("Deleted {} expired GeoIP bans", result.rows_affected);
+    }
+
+    let result = Mutation::delete_expired_url_filters(&state.db, now).await?;
+    if result.rows_affected > 0 {
+        info!("Deleted {} expired URL filters", result.rows_affected);
+    }
+
+    Ok(())
+}
+
+// Forget the submitter IP's of URL's older than the retention period
+async fn forget_ips(state: AppState) -> Result<(), JobError> {
+    let Some(retention) = state.env.ip_retention else {
+        return Ok(());
+    };
+
+    let result = Mutation::forget_url_ips(&state.db, OffsetDateTime::now_utc() - retention).await?;
+    if result.rows_affected > 0 {
+        info!("Forgot submitter IP's of {} URL's", result.rows_affected);
+    }
+
+    Ok(())
+}
+
+// Pick up bans, allows, and GeoIP databases changed outside of this process
+async fn reload_bans(state: AppState) -> Result<(), JobError> {
+    state.geoip.reload_changed().await?;
+    state.bancache.reload().await?;
+    Ok(())
+}
+
+// Start all of the jobs
+pub fn spawn_all(state: &AppState) -> Vec<JoinHandle<()>> {
+    let mut jobs = vec![
+        every(
+            "flush_hits",
+            state.env.hit_flush_interval,
+            state.clone(),
+            flush_hits,
+        ),
+        every(
+            "expire",
+            state.env.expiry_cleanup_interval,
+            state.clone(),
+            expire,
+        ),
+        every(
+            "reload_bans",
+            state.env.ban_reload_interval,
+            state.clone(),
+            reload_bans,
+        ),
+    ];
+
+    if state.env.ip_retention.is_some() {
+        jobs.push(every(
+            "forget_ips",
+            state.env.expiry_cleanup_interval,
+            state.clone(),
+            forget_ips,
+        ));
+    }
+
+    jobs
+}
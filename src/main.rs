@@ -24,17 +24,28 @@ use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 use crate::cli::run_command;
 
 mod auth;
+mod autoban;
 mod bancache;
+mod bancoalesce;
+mod banexport;
+mod blocklist;
 mod cli;
 mod csrf;
 mod env;
 mod err;
 mod generate;
+mod geoip;
+mod hits;
+mod ipstorage;
+mod jobs;
+mod shortener;
 mod state;
 mod urlcache;
+mod urlfilter;
 mod util;
 mod validators;
 mod web;
+mod wordcheck;
 
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
@@ -0,0 +1,129 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/shortener.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// Expand links made by other URL shorteners, so we shadify the real destination
+
+use reqwest::{header, redirect, Client};
+use time::Duration;
+use tracing::debug;
+use url::Url;
+
+use crate::validators::{validate_url, UrlPolicy};
+
+// How many shorteners we'll follow through before giving up
+const MAX_HOPS: usize = 5;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ShortenerError {
+    #[error("Could not contact URL shortener: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("URL shortener did not redirect anywhere")]
+    NoRedirect,
+
+    #[error("URL shortener gave a bad redirect")]
+    BadRedirect,
+
+    #[error("Invalid shortener resolver URL: {0}")]
+    Resolver(#[from] url::ParseError),
+
+    #[error("Too many URL shorteners in a row")]
+    TooManyHops,
+
+    #[error("Shortened URL goes somewhere not allowed: {}", .0.message.as_deref().unwrap_or("invalid URL"))]
+    Invalid(validator::ValidationError),
+}
+
+#[derive(Clone)]
+pub struct ShortenerResolver {
+    client: Client,
+    // If set, lookups are sent here instead, with the shortener's host in the Host header
+    resolve_via: Option<Url>,
+}
+
+impl ShortenerResolver {
+    pub(crate) fn new(
+        timeout: Duration,
+        resolve_via: Option<&str>,
+    ) -> Result<Self, ShortenerError> {
+        // We want to see the redirects, not follow them
+        let client = Client::builder()
+            .redirect(redirect::Policy::none())
+            .timeout(timeout.unsigned_abs())
+            .build()?;
+        let resolve_via = resolve_via.map(Url::parse).transpose()?;
+
+        Ok(Self {
+            client,
+            resolve_via,
+        })
+    }
+
+    // Find where a single shortened link goes
+    async fn lookup(&self, url: &Url) -> Result<Url, ShortenerError> {
+        let request = self.resolve_via.as_ref().map_or_else(
+            || self.client.get(url.clone()),
+            |via| {
+                let mut target = via.clone();
+                target.set_path(url.path());
+                target.set_query(url.query());
+
+                let host = url.host_str().unwrap_or_default();
+                let host = url
+                    .port()
+                    .map_or_else(|| host.to_string(), |port| format!("{host}:{port}"));
+                self.client.get(target).header(header::HOST, host)
+            },
+        );
+
+        let response = request.send().await?;
+        if !response.status().is_redirection() {
+            return Err(ShortenerError::NoRedirect);
+        }
+
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .ok_or(ShortenerError::NoRedirect)?
+            .to_str()
+            .map_err(|_| ShortenerError::BadRedirect)?;
+        url.join(location).map_err(|_| ShortenerError::BadRedirect)
+    }
+
+    // Follow a shortened link until it goes somewhere that isn't a shortener.
+    // Every step along the way has to pass the URL policy.
+    pub(crate) async fn expand(
+        &self,
+        url: &str,
+        policy: &UrlPolicy,
+    ) -> Result<String, ShortenerError> {
+        let mut current = Url::parse(url).map_err(|_| {
+            ShortenerError::Invalid(
+                validator::ValidationError::new("Invalid URL").with_message("Invalid URL".into()),
+            )
+        })?;
+        for _ in 0..=MAX_HOPS {
+            if !policy.is_shortener(&current) {
+                return Ok(current.into());
+            }
+
+            let next = self.lookup(&current).await?;
+            debug!("Expanded shortened URL {current} -> {next}");
+            validate_url(next.as_str(), policy).map_err(ShortenerError::Invalid)?;
+            current = next;
+        }
+
+        Err(ShortenerError::TooManyHops)
+    }
+}
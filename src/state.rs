@@ -18,7 +18,10 @@ use std::sync::Arc;
 
 use sea_orm::DbConn;
 
-use crate::{bancache::BanCache, env::Vars, urlcache::UrlCache};
+use crate::{
+    autoban::AutoBan, bancache::BanCache, env::Vars, geoip::GeoIp, hits::HitCounter,
+    ipstorage::IpStorage, shortener::ShortenerResolver, urlcache::UrlCache, validators::UrlPolicy,
+};
 
 // This is the struct that holds state for handlers
 #[allow(clippy::module_name_repetitions)]
@@ -28,6 +31,12 @@ pub struct AppState {
     // So this needs to be an Arc.
     pub(crate) db: Arc<DbConn>,
     pub(crate) env: Vars,
+    pub(crate) url_policy: Arc<UrlPolicy>,
     pub(crate) bancache: BanCache,
+    pub(crate) geoip: GeoIp,
+    pub(crate) autoban: AutoBan,
     pub(crate) urlcache: UrlCache,
+    pub(crate) shortener: ShortenerResolver,
+    pub(crate) hits: HitCounter,
+    pub(crate) ip_storage: IpStorage,
 }
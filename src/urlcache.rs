@@ -12,60 +12,73 @@
  * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
  */
 
-// Compiled regex caching
+// Compiled URL filter caching
 
 use std::sync::Arc;
 
 use moka::future::Cache;
-use regex::Regex;
 use sea_orm::{DbConn, DbErr};
-use time::Duration;
+use time::{Duration, OffsetDateTime};
 use tokio::sync::RwLock;
-use tracing::trace;
+use tracing::{debug, trace};
 
+use entity::url_filter;
 use service::Query;
 
+use crate::urlfilter::{FilterSet, Matcher, Target, UrlFilterError};
+
 #[derive(Debug, thiserror::Error)]
 pub enum UrlCacheError {
     #[error(transparent)]
     Db(#[from] DbErr),
 
     #[error(transparent)]
-    Regex(#[from] regex::Error),
+    Filter(#[from] UrlFilterError),
 
-    #[error("Regex not found: {}", .0)]
-    RegexNotFound(String),
+    #[error("Filter not found: {}", .0)]
+    FilterNotFound(i64),
 
-    #[error("Regex is duplicate: {}", .0)]
-    RegexDuplicated(String),
+    #[error("Filter is duplicate: {}", .0)]
+    FilterDuplicated(String),
 }
 
+// ID's of the filters matching a URL
+pub type FilterMatches = Arc<[i64]>;
+
 #[derive(Clone, Debug)]
 pub struct UrlCache {
-    // This makes sure we can still clone, yet we still point to the same Vec
-    regex: Arc<RwLock<Vec<Regex>>>,
-    cache: Cache<String, bool>, // Caches addresses
+    // This makes sure we can still clone, yet we still point to the same set.
+    // The set is rebuilt whenever a filter is added or removed.
+    filters: Arc<RwLock<FilterSet>>,
+    cache: Cache<String, FilterMatches>, // Caches addresses
     db: Arc<DbConn>,
 }
 
 impl UrlCache {
-    // Create a new UrlCache instance and initalise regexes from the database.
+    // Load every filter from the database that hasn't expired
+    async fn load_filters(db: &DbConn) -> Result<FilterSet, UrlCacheError> {
+        let now = OffsetDateTime::now_utc();
+        let filters = Query::fetch_all_url_filters(db)
+            .await?
+            .into_iter()
+            .map(|(url_filter, _)| url_filter)
+            .filter(|url_filter| url_filter.expires_at.map_or(true, |expiry| expiry > now))
+            .collect();
+        Ok(FilterSet::new(filters)?)
+    }
+
+    // Create a new UrlCache instance and initalise filters from the database.
     pub(crate) async fn new(
         db: Arc<DbConn>,
         entries: u64,
         ttl: Duration,
         idle: Duration,
     ) -> Result<Self, UrlCacheError> {
-        let mut regex = Vec::new();
-        for url_filter in Query::fetch_all_url_filters(&db).await? {
-            trace!("Adding URL filter {}", url_filter.0.filter);
-            let cmpreg = Regex::new(&url_filter.0.filter)?;
-            regex.push(cmpreg);
-        }
+        let filters = Self::load_filters(&db).await?;
 
         Ok(Self {
             // XXX - should these cache parameters be configurable?
-            regex: Arc::new(RwLock::new(regex)),
+            filters: Arc::new(RwLock::new(filters)),
             cache: Cache::builder()
                 .max_capacity(entries)
                 .time_to_live(ttl.unsigned_abs())
@@ -76,86 +89,125 @@ impl UrlCache {
         })
     }
 
-    // Sync the regex cache with the database, flushing all old entries
+    // Sync the filter cache with the database, flushing all old entries
     // This also flushes the URL cache.
-    pub(crate) async fn sync_regex_cache(&mut self) -> Result<(), UrlCacheError> {
-        let mut regex_vec = self.regex.write().await;
-        let mut new_regex = Vec::with_capacity(regex_vec.len());
-
-        for url_filter in Query::fetch_all_url_filters(&self.db).await? {
-            let cmpreg = Regex::new(&url_filter.0.filter)?;
-            new_regex.push(cmpreg);
-        }
-
-        regex_vec.clear();
-        regex_vec.extend(new_regex);
-        drop(regex_vec);
+    pub(crate) async fn sync_filter_cache(&mut self) -> Result<(), UrlCacheError> {
+        let mut filters = self.filters.write().await;
+        *filters = Self::load_filters(&self.db).await?;
+        drop(filters);
         self.cache.invalidate_all();
         Ok(())
     }
 
-    // Add one regex without flushing the entire cache.
+    // Add one filter without flushing the entire cache.
     // This operation also removes any matching URL's from the cache.
     // NOTE: this does not update the database
-    pub(crate) async fn add_regex_cache(&mut self, cmpreg: Regex) -> Result<(), UrlCacheError> {
-        let mut regex_vec = self.regex.write().await;
-        if regex_vec
+    pub(crate) async fn add_filter_cache(
+        &mut self,
+        url_filter: url_filter::Model,
+    ) -> Result<(), UrlCacheError> {
+        let matcher = Matcher::new(url_filter.kind, &url_filter.filter)?;
+        // An expired copy of the filter may not have been dropped yet
+        self.expire_filters().await?;
+        let mut filters = self.filters.write().await;
+        if filters
+            .filters()
             .iter()
-            .any(|ocmpreg| ocmpreg.as_str() == cmpreg.as_str())
+            .any(|ofilter| ofilter.filter == url_filter.filter && ofilter.kind == url_filter.kind)
         {
-            trace!("URL is duplicated: {}", cmpreg.as_str());
-            return Err(UrlCacheError::RegexDuplicated(cmpreg.as_str().to_string()));
+            trace!("URL filter is duplicated: {}", url_filter.filter);
+            return Err(UrlCacheError::FilterDuplicated(url_filter.filter));
         }
 
-        trace!("Adding URL filter to regex cache {}", cmpreg.as_str());
+        trace!("Adding URL filter to filter cache {}", url_filter.filter);
 
-        regex_vec.push(cmpreg.clone());
-        drop(regex_vec);
+        let mut new_filters = filters.filters().to_vec();
+        new_filters.push(url_filter);
+        *filters = FilterSet::new(new_filters)?;
+        drop(filters);
         self.cache
-            .invalidate_entries_if(move |k, _| cmpreg.is_match(k))
+            .invalidate_entries_if(move |k, _| matcher.is_match(&Target::new(k)))
             .expect("Could not invalidate cache");
         Ok(())
     }
 
-    // Remove one regex without flushing the entire cache.
-    // This operation also removes any matching URL's from the cache.
+    // Remove one filter without flushing the entire cache.
+    // This operation also removes any URL's it matched from the cache.
     // NOTE: this does not update the database
-    pub(crate) async fn remove_regex_cache(&mut self, regstr: &str) -> Result<(), UrlCacheError> {
-        // Invariant: regexes are not duplicated
-        let mut regex_vec = self.regex.write().await;
-        let pos = regex_vec
+    pub(crate) async fn remove_filter_cache(&mut self, id: i64) -> Result<(), UrlCacheError> {
+        let mut filters = self.filters.write().await;
+        let mut new_filters = filters.filters().to_vec();
+        let pos = new_filters
             .iter()
-            .position(|cmpreg| cmpreg.as_str() == regstr)
-            .ok_or_else(|| UrlCacheError::RegexNotFound(regstr.to_string()))?;
-        let cmpreg = regex_vec[pos].clone();
-        regex_vec.swap_remove(pos);
-        drop(regex_vec);
+            .position(|ofilter| ofilter.id == id)
+            .ok_or(UrlCacheError::FilterNotFound(id))?;
+        let url_filter = new_filters.swap_remove(pos);
+        *filters = FilterSet::new(new_filters)?;
+        drop(filters);
         self.cache
-            .invalidate_entries_if(move |k, _| cmpreg.is_match(k))
+            .invalidate_entries_if(move |_, matched| matched.contains(&id))
             .expect("Could not invalidate cache");
 
-        trace!("Removing URL filter from regex cache {}", regstr);
+        trace!(
+            "Removing URL filter from filter cache {}",
+            url_filter.filter
+        );
         Ok(())
     }
 
-    // Check URL against cache.
-    // If not found, it will check the regexes, and cache the result.
-    // Returns true if found in the cache, false otherwise.
-    pub(crate) async fn check_url_banned(&self, url: &str) -> Result<bool, UrlCacheError> {
-        if let Some(is_match) = self.cache.get(url).await {
-            trace!("Cached URL ban result for \"{}\": {:?}", url, is_match);
-            return Ok(is_match);
+    // Drop filters that have expired, along with any cached URL's they matched.
+    // The database copies are cleaned up separately, so there's no need to go to it here.
+    async fn expire_filters(&self) -> Result<(), UrlCacheError> {
+        let now = OffsetDateTime::now_utc();
+        if !self.filters.read().await.has_expired(now) {
+            return Ok(());
         }
 
-        let regex_vec = self.regex.read().await;
-        let is_match = regex_vec
+        let mut filters = self.filters.write().await;
+        // Someone else may have beaten us to it
+        if !filters.has_expired(now) {
+            return Ok(());
+        }
+
+        let (expired, kept): (Vec<_>, Vec<_>) = filters
+            .filters()
             .iter()
-            .map(|cmpreg| cmpreg.is_match(url))
-            .any(|x| x);
-        drop(regex_vec);
+            .cloned()
+            .partition(|url_filter| url_filter.expires_at.is_some_and(|expiry| expiry <= now));
+        *filters = FilterSet::new(kept)?;
+        drop(filters);
+
+        let expired: Vec<i64> = expired
+            .into_iter()
+            .map(|url_filter| url_filter.id)
+            .collect();
+        debug!("URL filters expired: {expired:?}");
+        self.cache
+            .invalidate_entries_if(move |_, matched| matched.iter().any(|id| expired.contains(id)))
+            .expect("Could not invalidate cache");
+        Ok(())
+    }
+
+    // Check a URL against the filters, bypassing the cache, and without caching the result.
+    // Returns the ID's of all matching filters.
+    pub(crate) async fn test_url(&self, url: &str) -> Result<FilterMatches, UrlCacheError> {
+        self.expire_filters().await?;
+        Ok(self.filters.read().await.matches(&Target::new(url)).into())
+    }
+
+    // Check URL against cache.
+    // If not found, it will check the filters, and cache the result.
+    // Returns the ID's of all matching filters; if there are none, the URL is allowed.
+    pub(crate) async fn check_url_banned(&self, url: &str) -> Result<FilterMatches, UrlCacheError> {
+        self.expire_filters().await?;
+        if let Some(matched) = self.cache.get(url).await {
+            trace!("Cached URL ban result for \"{}\": {:?}", url, matched);
+            return Ok(matched);
+        }
 
-        trace!("Uncached URL ban result for \"{}\": {:?}", url, is_match);
-        self.cache.insert(url.to_string(), is_match).await;
-        Ok(is_match)
+        let matched = self.test_url(url).await?;
+        trace!("Uncached URL ban result for \"{}\": {:?}", url, matched);
+        self.cache.insert(url.to_string(), matched.clone()).await;
+        Ok(matched)
     }
 }
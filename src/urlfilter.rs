@@ -0,0 +1,287 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/urlfilter.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// URL filters, and how each kind matches.
+//
+// Regex filters match the URL as submitted, like they always have. Everything else matches against
+// the parsed URL, so admins don't have to worry about case, escaping, or punycode.
+
+use std::collections::HashMap;
+
+use entity::{sea_orm_active_enums::FilterKind, url_filter};
+use regex::{Regex, RegexSet};
+use time::OffsetDateTime;
+use url::{Host, Url};
+
+use crate::validators::url_host;
+
+// Every kind of filter, in the order we show them
+pub const KINDS: [FilterKind; 5] = [
+    FilterKind::Regex,
+    FilterKind::Host,
+    FilterKind::Domain,
+    FilterKind::PathPrefix,
+    FilterKind::Substring,
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum UrlFilterError {
+    #[error("Filter cannot be empty")]
+    Empty,
+
+    #[error(transparent)]
+    Regex(#[from] regex::Error),
+
+    #[error("Invalid host: {}", .0)]
+    Host(String),
+
+    #[error("Invalid path prefix: {}", .0)]
+    PathPrefix(String),
+}
+
+// Human-readable name of a filter kind
+pub const fn describe(kind: FilterKind) -> &'static str {
+    match kind {
+        FilterKind::Regex => "Regex",
+        FilterKind::Host => "Exact host",
+        FilterKind::Domain => "Domain and subdomains",
+        FilterKind::PathPrefix => "Path prefix",
+        FilterKind::Substring => "Substring",
+    }
+}
+
+// Parse a host the way url_host does
+fn normalize_host(host: &str) -> Result<String, UrlFilterError> {
+    let host = host.trim().trim_end_matches('.');
+    Host::parse(host)
+        .map(|h| h.to_string().to_lowercase())
+        .map_err(|_| UrlFilterError::Host(host.to_string()))
+}
+
+// Check a filter is valid for its kind, and put it in the form we store.
+// Equivalent filters are stored the same way, so duplicates can be caught.
+pub fn normalize(kind: FilterKind, filter: &str) -> Result<String, UrlFilterError> {
+    let filter = filter.trim();
+    if filter.is_empty() {
+        return Err(UrlFilterError::Empty);
+    }
+
+    match kind {
+        FilterKind::Regex => {
+            Regex::new(filter)?;
+            Ok(filter.to_string())
+        }
+        FilterKind::Host | FilterKind::Domain => normalize_host(filter),
+        FilterKind::PathPrefix => {
+            // The scheme doesn't matter, but the URL parser needs one
+            let with_scheme = if filter.contains("://") {
+                filter.to_string()
+            } else {
+                format!("http://{filter}")
+            };
+            let url = Url::parse(&with_scheme)
+                .map_err(|_| UrlFilterError::PathPrefix(filter.to_string()))?;
+            let host =
+                url_host(&url).ok_or_else(|| UrlFilterError::PathPrefix(filter.to_string()))?;
+            Ok(format!("{host}{}", url.path()))
+        }
+        FilterKind::Substring => Ok(filter.to_lowercase()),
+    }
+}
+
+// A URL broken down for matching
+pub struct Target<'a> {
+    raw: &'a str,
+    host: Option<String>,
+    path: String,
+    lower: String,
+}
+
+impl<'a> Target<'a> {
+    pub(crate) fn new(raw: &'a str) -> Self {
+        Url::parse(raw).map_or_else(
+            |_| Self {
+                raw,
+                host: None,
+                path: String::new(),
+                lower: raw.to_lowercase(),
+            },
+            |url| Self {
+                raw,
+                host: url_host(&url),
+                path: url.path().to_string(),
+                lower: url.as_str().to_lowercase(),
+            },
+        )
+    }
+}
+
+// A compiled filter
+#[derive(Clone, Debug)]
+pub enum Matcher {
+    Regex(Regex),
+    Host(String),
+    Domain(String),
+    PathPrefix { host: String, path: String },
+    Substring(String),
+}
+
+impl Matcher {
+    pub(crate) fn new(kind: FilterKind, filter: &str) -> Result<Self, UrlFilterError> {
+        let filter = normalize(kind, filter)?;
+        Ok(match kind {
+            FilterKind::Regex => Self::Regex(Regex::new(&filter)?),
+            FilterKind::Host => Self::Host(filter),
+            FilterKind::Domain => Self::Domain(filter),
+            FilterKind::PathPrefix => {
+                // Normalised path prefixes always have a path
+                let pos = filter.find('/').unwrap_or(filter.len());
+                let (host, path) = filter.split_at(pos);
+                Self::PathPrefix {
+                    host: host.to_string(),
+                    path: path.to_string(),
+                }
+            }
+            FilterKind::Substring => Self::Substring(filter),
+        })
+    }
+
+    pub(crate) fn is_match(&self, target: &Target) -> bool {
+        match self {
+            Self::Regex(cmpreg) => cmpreg.is_match(target.raw),
+            Self::Host(host) => target.host.as_ref() == Some(host),
+            Self::Domain(domain) => target.host.as_ref().is_some_and(|host| {
+                host.strip_suffix(domain.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.ends_with('.'))
+            }),
+            Self::PathPrefix { host, path } => {
+                target.host.as_ref() == Some(host) && target.path.starts_with(path.as_str())
+            }
+            Self::Substring(substring) => target.lower.contains(substring.as_str()),
+        }
+    }
+}
+
+// Every filter compiled together, so a URL is tested once no matter how many filters there are.
+// Regexes and substrings go into regex sets; the rest are lookups on the URL's host.
+#[derive(Clone, Debug)]
+pub struct FilterSet {
+    filters: Vec<url_filter::Model>,
+    regex: RegexSet,
+    regex_ids: Vec<i64>,
+    substring: RegexSet,
+    substring_ids: Vec<i64>,
+    hosts: HashMap<String, Vec<i64>>,
+    domains: HashMap<String, Vec<i64>>,
+    path_prefixes: HashMap<String, Vec<(String, i64)>>,
+    // When the first of the filters expires, if any of them do
+    next_expiry: Option<OffsetDateTime>,
+}
+
+impl FilterSet {
+    pub(crate) fn new(filters: Vec<url_filter::Model>) -> Result<Self, UrlFilterError> {
+        let mut regexes = Vec::new();
+        let mut regex_ids = Vec::new();
+        let mut substrings = Vec::new();
+        let mut substring_ids = Vec::new();
+        let mut hosts: HashMap<String, Vec<i64>> = HashMap::new();
+        let mut domains: HashMap<String, Vec<i64>> = HashMap::new();
+        let mut path_prefixes: HashMap<String, Vec<(String, i64)>> = HashMap::new();
+
+        for filter in &filters {
+            match Matcher::new(filter.kind, &filter.filter)? {
+                Matcher::Regex(cmpreg) => {
+                    regexes.push(cmpreg.as_str().to_string());
+                    regex_ids.push(filter.id);
+                }
+                Matcher::Host(host) => hosts.entry(host).or_default().push(filter.id),
+                Matcher::Domain(domain) => domains.entry(domain).or_default().push(filter.id),
+                Matcher::PathPrefix { host, path } => {
+                    path_prefixes
+                        .entry(host)
+                        .or_default()
+                        .push((path, filter.id));
+                }
+                Matcher::Substring(substring) => {
+                    substrings.push(regex::escape(&substring));
+                    substring_ids.push(filter.id);
+                }
+            }
+        }
+
+        let next_expiry = filters.iter().filter_map(|f| f.expires_at).min();
+
+        Ok(Self {
+            filters,
+            regex: RegexSet::new(regexes)?,
+            regex_ids,
+            substring: RegexSet::new(substrings)?,
+            substring_ids,
+            hosts,
+            domains,
+            path_prefixes,
+            next_expiry,
+        })
+    }
+
+    // The filters this set was built from
+    pub(crate) fn filters(&self) -> &[url_filter::Model] {
+        &self.filters
+    }
+
+    // True if any filter in this set has expired
+    pub(crate) fn has_expired(&self, now: OffsetDateTime) -> bool {
+        self.next_expiry.is_some_and(|expiry| expiry <= now)
+    }
+
+    // Find the ID's of every filter matching a URL, in ascending order
+    pub(crate) fn matches(&self, target: &Target) -> Vec<i64> {
+        let mut ids: Vec<i64> = self
+            .regex
+            .matches(target.raw)
+            .iter()
+            .map(|i| self.regex_ids[i])
+            .chain(
+                self.substring
+                    .matches(&target.lower)
+                    .iter()
+                    .map(|i| self.substring_ids[i]),
+            )
+            .collect();
+
+        if let Some(host) = &target.host {
+            ids.extend(self.hosts.get(host).into_iter().flatten());
+
+            // Try the host, then each domain above it
+            let mut domain = Some(host.as_str());
+            while let Some(current) = domain {
+                ids.extend(self.domains.get(current).into_iter().flatten());
+                domain = current.split_once('.').map(|(_, rest)| rest);
+            }
+
+            ids.extend(
+                self.path_prefixes
+                    .get(host)
+                    .into_iter()
+                    .flatten()
+                    .filter(|(path, _)| target.path.starts_with(path.as_str()))
+                    .map(|(_, id)| id),
+            );
+        }
+
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+}
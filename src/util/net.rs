@@ -54,6 +54,35 @@ pub fn vec_to_ipaddr(addr: Vec<u8>) -> Result<IpAddr, AddressError> {
     Ok(addr.to_canonical())
 }
 
+// Stored ranges are 16 bytes, with IPv4 mapped into IPv6.
+// As integers, they can be compared and sorted without caring about the address family.
+pub fn range_to_int(begin: Vec<u8>, end: Vec<u8>) -> Result<(u128, u128), AddressError> {
+    let to_int = |octets: Vec<u8>| {
+        <[u8; 16]>::try_from(octets)
+            .map(u128::from_be_bytes)
+            .map_err(|octets| AddressError::IncorrectSize(octets.len()))
+    };
+    Ok((to_int(begin)?, to_int(end)?))
+}
+
+// The integer an address has in stored ranges
+pub fn ipaddr_to_int(addr: IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(addr) => addr.to_ipv6_mapped().into(),
+        IpAddr::V6(addr) => addr.into(),
+    }
+}
+
+// The fewest networks covering a stored range, given as integers.
+// Ranges entirely within the IPv4 mapped block come out as IPv4 networks.
+pub fn int_range_networks(begin: u128, end: u128) -> Result<Vec<IpNetwork>, NetworkPrefixError> {
+    let (begin, end) = (Ipv6Addr::from(begin), Ipv6Addr::from(end));
+    match (begin.to_ipv4_mapped(), end.to_ipv4_mapped()) {
+        (Some(begin), Some(end)) => find_networks(begin.into(), end.into()),
+        _ => find_networks(begin.into(), end.into()),
+    }
+}
+
 // Given an IP range, find the given IpNetworks (it may encompass more than one)
 pub fn find_networks(start: IpAddr, end: IpAddr) -> Result<Vec<IpNetwork>, NetworkPrefixError> {
     let res = match (start, end) {
@@ -66,18 +95,23 @@ pub fn find_networks(start: IpAddr, end: IpAddr) -> Result<Vec<IpNetwork>, Netwo
             while start_int <= end_int {
                 // SAFETY: safe cast, we can never have > 255
                 #[allow(clippy::cast_possible_truncation)]
-                let nbits = start_int
-                    .trailing_zeros()
-                    .min((end_int - start_int + 1).bit_length() - 1)
-                    as u8;
+                let nbits = start_int.trailing_zeros().min(
+                    (end_int - start_int)
+                        .checked_add(1)
+                        .map_or(32, |size| size.bit_length() - 1),
+                ) as u8;
                 res.push(IpNetwork::new(
                     IpAddr::V4(Ipv4Addr::from(start_int)),
                     32 - nbits,
                 )?);
-                start_int += 1 << nbits;
-                if start_int - 1 == u32::MAX {
+                // Stop once we reach the end of the address space
+                let Some(next) = 1u32
+                    .checked_shl(nbits.into())
+                    .and_then(|size| start_int.checked_add(size))
+                else {
                     break;
-                }
+                };
+                start_int = next;
             }
 
             res
@@ -91,18 +125,23 @@ pub fn find_networks(start: IpAddr, end: IpAddr) -> Result<Vec<IpNetwork>, Netwo
             while start_int <= end_int {
                 // SAFETY: safe cast, we can never have > 255
                 #[allow(clippy::cast_possible_truncation)]
-                let nbits = start_int
-                    .trailing_zeros()
-                    .min((end_int - start_int + 1).bit_length() - 1)
-                    as u8;
+                let nbits = start_int.trailing_zeros().min(
+                    (end_int - start_int)
+                        .checked_add(1)
+                        .map_or(128, |size| size.bit_length() - 1),
+                ) as u8;
                 res.push(IpNetwork::new(
                     IpAddr::V6(Ipv6Addr::from(start_int)),
                     128 - nbits,
                 )?);
-                start_int += 1 << nbits;
-                if start_int - 1 == u128::MAX {
+                // Stop once we reach the end of the address space
+                let Some(next) = 1u128
+                    .checked_shl(nbits.into())
+                    .and_then(|size| start_int.checked_add(size))
+                else {
                     break;
-                }
+                };
+                start_int = next;
             }
 
             res
@@ -119,3 +158,44 @@ pub fn find_networks(start: IpAddr, end: IpAddr) -> Result<Vec<IpNetwork>, Netwo
 
     Ok(res)
 }
+
+// Check if an address is loopback, private, link-local, multicast, or otherwise not reachable on
+// the public internet. Used to keep links from pointing at our own network.
+pub const fn is_internal_addr(addr: IpAddr) -> bool {
+    match addr.to_canonical() {
+        IpAddr::V4(addr) => {
+            let [a, b, ..] = addr.octets();
+            addr.is_loopback()
+                || addr.is_private()
+                || addr.is_link_local()
+                || addr.is_unspecified()
+                || addr.is_documentation()
+                || addr.is_multicast()
+                // Carrier-grade NAT (100.64.0.0/10)
+                || (a == 100 && (b & 0xc0) == 64)
+                // "This network" (0.0.0.0/8)
+                || a == 0
+                // Reserved (240.0.0.0/4), which takes in broadcast
+                || a >= 240
+        }
+        IpAddr::V6(addr) => {
+            let segments = addr.segments();
+            addr.is_loopback()
+                || addr.is_unspecified()
+                || addr.is_multicast()
+                // Unique local (fc00::/7)
+                || (segments[0] & 0xfe00) == 0xfc00
+                // Link-local (fe80::/10)
+                || (segments[0] & 0xffc0) == 0xfe80
+                // Documentation (2001:db8::/32)
+                || (segments[0] == 0x2001 && segments[1] == 0x0db8)
+                // NAT64 (64:ff9b::/96), which a local translator turns into any IPv4 address
+                || (segments[0] == 0x64
+                    && segments[1] == 0xff9b
+                    && segments[2] == 0
+                    && segments[3] == 0
+                    && segments[4] == 0
+                    && segments[5] == 0)
+        }
+    }
+}
@@ -26,6 +26,12 @@ use time::{
 
 use super::math::FloatMathUtil;
 
+// Parse a duration written by a human, in systemd's syntax (like "1h 30m" or "7d").
+// Used for both configuration and durations entered in the admin pages.
+pub fn parse_duration(duration: &str) -> Result<Duration, systemd_duration::error::Error> {
+    systemd_duration::time::parse(duration.trim())
+}
+
 // Convert a Duration into something for humans.
 // This implementation is heavily modified from the time crate.
 pub fn humanize_duration(duration: Duration) -> String {
@@ -100,14 +106,18 @@ pub fn humanize_duration(duration: Duration) -> String {
 // (This means safe for URL's *and* HTML, without escaping)
 pub struct WebsafeAlphabet;
 
+impl WebsafeAlphabet {
+    // Number of distinct characters we generate
+    pub const SIZE: u32 = 26 + 26 + 10 + 7;
+}
+
 impl Distribution<u8> for WebsafeAlphabet {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> u8 {
         const GEN_ASCII_STR_CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
                 abcdefghijklmnopqrstuvwxyz\
                 0123456789\
                 $-_+!*,";
-        const MAX: usize = 26 + 26 + 10 + 7;
-        let range = Lazy::new(|| Uniform::new(0, MAX));
+        let range = Lazy::new(|| Uniform::new(0, Self::SIZE as usize));
 
         // SAFETY: guaranteed to be within bounds
         unsafe { *GEN_ASCII_STR_CHARSET.get_unchecked((*range).sample(rng)) }
@@ -13,43 +13,205 @@
  */
 
 use ipnetwork::IpNetwork;
-
+use once_cell::sync::Lazy;
+use publicsuffix::{List, Psl};
+use serde::Deserialize;
+use time::{Duration, OffsetDateTime};
 use url::{Host, Url};
 use validator::ValidationError;
 
-// Ensure a URL is a valid type
-pub fn validate_url(url: &str) -> Result<(), ValidationError> {
+use crate::util::{net::is_internal_addr, string::parse_duration};
+
+// Offline copy of https://publicsuffix.org/list/public_suffix_list.dat
+// It's under the MPL 2.0, not CC0; see data/LICENSE-MPL-2.0.
+static PUBLIC_SUFFIXES: Lazy<List> = Lazy::new(|| {
+    include_str!("../data/public_suffix_list.dat")
+        .parse()
+        .expect("Embedded public suffix list is invalid")
+});
+
+// How hard to look at the TLD of a URL's host
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TldCheck {
+    // Anything goes
+    None,
+    // The host must have a dot followed by something
+    #[default]
+    Naive,
+    // The host must be under a suffix in the public suffix list
+    PublicSuffix,
+}
+
+// What to do with links to other URL shorteners
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ShortenerPolicy {
+    // Refuse them outright
+    #[default]
+    Reject,
+    // Look up where they go, and shadify that instead
+    Expand,
+}
+
+// What URL's we accept for shadifying
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Debug)]
+pub struct UrlPolicy {
+    pub(crate) schemes: Vec<String>,
+    pub(crate) max_length: usize,
+    pub(crate) allow_ip_hosts: bool,
+    pub(crate) allow_ports: bool,
+    pub(crate) allow_credentials: bool,
+    pub(crate) allow_private: bool,
+    pub(crate) tld_check: TldCheck,
+    // Our own hosts, without ports
+    pub(crate) own_hosts: Vec<String>,
+    pub(crate) shortener_domains: Vec<String>,
+    pub(crate) shortener_policy: ShortenerPolicy,
+}
+
+// Check if a host is one of the given domains, or a subdomain of one
+fn host_in(host: &str, domains: &[String]) -> bool {
+    let host = host.strip_suffix('.').unwrap_or(host);
+    domains.iter().any(|domain| {
+        host.strip_suffix(domain.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.ends_with('.'))
+    })
+}
+
+// Get the host of a URL the way we compare it: parsed, in lowercase, with no port or trailing dot
+pub fn url_host(url: &Url) -> Option<String> {
+    let host = url.host_str().filter(|h| !h.is_empty())?;
+    let host = host.strip_suffix('.').unwrap_or(host);
+    Host::parse(host).ok().map(|h| h.to_string().to_lowercase())
+}
+
+// Strip the port from a host:port pair, if there is one
+pub fn strip_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        return host.find(']').map_or(host, |end| &host[..=end]);
+    }
+
+    match host.rsplit_once(':') {
+        Some((name, port)) if !name.contains(':') && port.bytes().all(|b| b.is_ascii_digit()) => {
+            name
+        }
+        _ => host,
+    }
+}
+
+impl UrlPolicy {
+    // Check if a URL points at a known URL shortener
+    pub(crate) fn is_shortener(&self, url: &Url) -> bool {
+        url_host(url).is_some_and(|host| host_in(&host, &self.shortener_domains))
+    }
+}
+
+// Default ports for schemes the url crate doesn't know about.
+// The url crate already drops default ports for the schemes it does know.
+fn default_port(scheme: &str) -> Option<u16> {
+    match scheme {
+        "ftps" => Some(990),
+        "gemini" => Some(1965),
+        "gopher" => Some(70),
+        "irc" | "irc6" => Some(6667),
+        "ircs" => Some(6697),
+        "mumble" => Some(64738),
+        "teamspeak" => Some(9987),
+        "xmpp" | "jabber" => Some(5222),
+        _ => None,
+    }
+}
+
+fn check_tld(host: &str, tld_check: TldCheck) -> Result<(), ValidationError> {
+    let err = ValidationError::new("Invalid URL");
+    match tld_check {
+        TldCheck::None => Ok(()),
+        TldCheck::Naive => {
+            let pos = host
+                .rfind('.')
+                .ok_or_else(|| err.clone().with_message("No TLD".into()))?;
+            if host.len() - pos < 2 {
+                return Err(err.with_message("Invalid TLD".into()));
+            }
+
+            Ok(())
+        }
+        TldCheck::PublicSuffix => {
+            let host = host.strip_suffix('.').unwrap_or(host);
+            match PUBLIC_SUFFIXES.domain(host.as_bytes()) {
+                Some(domain) if domain.suffix().is_known() => Ok(()),
+                Some(_) => Err(err.with_message("Unknown TLD".into())),
+                None => Err(err.with_message("Host is a public suffix".into())),
+            }
+        }
+    }
+}
+
+// Ensure a URL is a valid type, and allowed by the policy
+pub fn validate_url(url: &str, policy: &UrlPolicy) -> Result<(), ValidationError> {
     let err = ValidationError::new("Invalid URL");
-    if url.len() > 2048 {
+    if url.len() > policy.max_length {
         return Err(err.with_message("URL is too long".into()));
     }
 
     let url_parsed = Url::parse(url).map_err(|_| err.clone().with_message("Invalid URL".into()))?;
-    if !url_parsed.has_host() {
-        Err(err.clone().with_message("No host found".into()))?;
-    }
-
-    match url_parsed.scheme() {
-        "ftp" | "ftps" | "gemini" | "gopher" | "http" | "https" | "irc" | "irc6" | "ircs"
-        | "jabber" | "matrix" | "mumble" | "mxc" | "spotify" | "teamspeak" | "xmpp" => {
-            match url_parsed
-                .host()
-                .ok_or_else(|| err.clone().with_message("No host found".into()))?
-            {
-                Host::Ipv4(_) | Host::Ipv6(_) => Ok(()),
-                Host::Domain(host_str) => {
-                    let pos = host_str
-                        .rfind('.')
-                        .ok_or_else(|| err.clone().with_message("No TLD".into()))?;
-                    if host_str.len() - pos < 2 {
-                        return Err(err.clone().with_message("Invalid TLD".into()));
-                    }
-
-                    Ok(())
-                }
+    if !policy.schemes.iter().any(|s| s == url_parsed.scheme()) {
+        return Err(err.with_message("Bad URL scheme".into()));
+    }
+
+    // Hosts of schemes the url crate doesn't know are left opaque, so parse them ourselves.
+    let host = url_parsed
+        .host_str()
+        .filter(|h| !h.is_empty())
+        .ok_or_else(|| err.clone().with_message("No host found".into()))?;
+    let host = Host::parse(host).map_err(|_| err.clone().with_message("Invalid host".into()))?;
+
+    // No links to links
+    let host_str = host.to_string().to_lowercase();
+    if host_in(&host_str, &policy.own_hosts) {
+        return Err(err.with_message("Links to this site are not allowed".into()));
+    }
+    if policy.shortener_policy == ShortenerPolicy::Reject
+        && host_in(&host_str, &policy.shortener_domains)
+    {
+        return Err(err.with_message("Links to other URL shorteners are not allowed".into()));
+    }
+
+    if !policy.allow_credentials
+        && (!url_parsed.username().is_empty() || url_parsed.password().is_some())
+    {
+        return Err(err.with_message("URL contains credentials".into()));
+    }
+
+    if !policy.allow_ports
+        && url_parsed
+            .port()
+            .is_some_and(|p| Some(p) != default_port(url_parsed.scheme()))
+    {
+        return Err(err.with_message("URL has a non-default port".into()));
+    }
+
+    match host {
+        Host::Ipv4(_) | Host::Ipv6(_) if !policy.allow_ip_hosts => {
+            Err(err.with_message("IP addresses are not allowed as hosts".into()))
+        }
+        Host::Ipv4(addr) if !policy.allow_private && is_internal_addr(addr.into()) => {
+            Err(err.with_message("Private addresses are not allowed".into()))
+        }
+        Host::Ipv6(addr) if !policy.allow_private && is_internal_addr(addr.into()) => {
+            Err(err.with_message("Private addresses are not allowed".into()))
+        }
+        Host::Ipv4(_) | Host::Ipv6(_) => Ok(()),
+        Host::Domain(_) => {
+            let bare = host_str.strip_suffix('.').unwrap_or(&host_str);
+            if !policy.allow_private && (bare == "localhost" || bare.ends_with(".localhost")) {
+                return Err(err.with_message("Private addresses are not allowed".into()));
             }
+
+            check_tld(&host_str, policy.tld_check)
         }
-        _ => Err(err.with_message("Bad URL scheme".into())),
     }
 }
 
@@ -61,3 +223,38 @@ pub fn validate_network(network: &str) -> Result<(), ValidationError> {
     })?;
     Ok(())
 }
+
+// Ensure a duration is parseable and not zero.
+// Empty durations are allowed, and mean forever.
+pub fn validate_duration(duration: &str) -> Result<(), ValidationError> {
+    if duration.trim().is_empty() {
+        return Ok(());
+    }
+
+    let duration = parse_duration(duration).map_err(|e| {
+        ValidationError::new("Invalid duration")
+            .with_message(format!("Error with duration: {e}").into())
+    })?;
+    if duration.is_zero() {
+        return Err(
+            ValidationError::new("Invalid duration").with_message("Duration cannot be zero".into())
+        );
+    }
+    if OffsetDateTime::now_utc().checked_add(duration).is_none() {
+        return Err(
+            ValidationError::new("Invalid duration").with_message("Duration is too long".into())
+        );
+    }
+
+    Ok(())
+}
+
+// Validate the period of a recurring job, which can't run every zero seconds
+pub fn validate_interval(interval: &Duration) -> Result<(), ValidationError> {
+    if !interval.is_positive() {
+        return Err(ValidationError::new("Invalid interval")
+            .with_message("Interval must be longer than zero".into()));
+    }
+
+    Ok(())
+}
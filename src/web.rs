@@ -12,10 +12,11 @@
  * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
  */
 
-pub use app::{App, RuntimeError};
+pub use app::{is_reserved_path, App, ReservedPath, Routes, RuntimeError};
 
 mod admin;
 mod app;
+mod banscope;
 mod fallback;
 mod files;
 mod submission;
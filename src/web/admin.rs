@@ -13,20 +13,33 @@
  */
 
 mod auth;
+mod cidr_allow;
 mod cidr_ban;
+mod export;
+mod filter_tester;
+mod geo_ban;
+mod import;
 mod index;
+mod ip;
+mod render;
 mod url_filter;
 mod urls;
+mod word_report;
 
-use axum::Router;
+use crate::web::Routes;
 
-use crate::state::AppState;
-
-pub fn router() -> Router<AppState> {
-    Router::new()
+pub fn router() -> Routes {
+    Routes::new()
         .merge(auth::router())
+        .merge(cidr_allow::router())
         .merge(cidr_ban::router())
+        .merge(export::router())
+        .merge(filter_tester::router())
+        .merge(geo_ban::router())
+        .merge(import::router())
         .merge(index::router())
+        .merge(ip::router())
         .merge(urls::router())
         .merge(url_filter::router())
+        .merge(word_report::router())
 }
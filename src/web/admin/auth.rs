@@ -19,7 +19,7 @@ use axum::{
     extract::State,
     response::{IntoResponse, Redirect, Response},
     routing::{get, post},
-    Form, Router,
+    Form,
 };
 use axum_messages::{Message, Messages};
 use tower_sessions::Session;
@@ -30,6 +30,7 @@ use crate::{
     csrf::SessionData,
     err::AppError,
     state::AppState,
+    web::Routes,
 };
 
 // Admin login portal
@@ -41,8 +42,8 @@ struct LoginTemplate {
     sitename: String,
 }
 
-pub fn router() -> Router<AppState> {
-    Router::new()
+pub fn router() -> Routes {
+    Routes::new()
         .route("/login", post(self::post::login))
         .route("/login", get(self::get::login))
         .route("/logout", get(self::get::logout))
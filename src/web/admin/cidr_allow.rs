@@ -0,0 +1,214 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/web/admin/cidr_allow.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// CIDR allow routes, for exceptions to CIDR bans
+
+use std::str::FromStr;
+
+use askama_axum::Template;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form,
+};
+use axum_messages::{Message, Messages};
+use ipnetwork::IpNetwork;
+use sea_orm::SqlErr;
+use serde::Deserialize;
+use time::OffsetDateTime;
+use tower_sessions::Session;
+use tracing::{debug, warn};
+use validator::Validate;
+
+use entity::{cidr_allow, user};
+use service::{Mutation, Query};
+
+use crate::{
+    auth::AuthSession,
+    csrf::SessionData,
+    err::AppError,
+    state::AppState,
+    util::{
+        net::{find_networks, vec_to_ipaddr},
+        string,
+    },
+    validators::validate_network,
+    web::Routes,
+};
+
+use super::render;
+
+// CIDR allow listing page (also submission)
+#[derive(Template)]
+#[template(path = "admin/cidr_allow.html")]
+struct CidrAllowsTemplate<'a> {
+    authenticity_token: &'a str,
+    messages: Vec<Message>,
+    sitename: &'a str,
+    cidr_allows: Vec<(cidr_allow::Model, Option<user::Model>)>,
+}
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+struct AllowForm {
+    authenticity_token: String,
+    #[validate(custom(function = validate_network))]
+    range: String,
+    reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DeleteForm {
+    authenticity_token: String,
+    id: i64,
+}
+
+pub fn router() -> Routes {
+    Routes::new()
+        .route("/admin/cidr_allows", get(self::get::cidr_allows))
+        .route("/admin/cidr_allows", post(self::post::cidr_allows))
+        .route("/admin/cidr_allows/delete", post(self::post::delete))
+}
+
+mod post {
+    use super::{
+        debug, find_networks, vec_to_ipaddr, warn, AllowForm, AppError, AppState, AuthSession,
+        DeleteForm, Form, FromStr, IntoResponse, IpNetwork, Messages, Mutation, Query, Redirect,
+        Response, Session, SessionData, SqlErr, State, Validate,
+    };
+
+    pub(super) async fn cidr_allows(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+        Form(allow_form): Form<AllowForm>,
+    ) -> Result<Response, AppError> {
+        SessionData::check_session(&session, &allow_form.authenticity_token).await?;
+
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to add a cidr_allow");
+            return Err(AppError::Unauthorized);
+        };
+
+        if let Err(e) = allow_form.validate() {
+            // Failed the validation checks
+            let error_reason = e
+                .field_errors()
+                .get("range")
+                .map_or_else(|| "Unknown error".to_string(), |v| v[0].to_string());
+            debug!(
+                "Invalid range ({}) submitted from user {}: {error_reason}",
+                allow_form.range, user.0.username
+            );
+            messages.error(format!("Invalid range: {error_reason}").as_str());
+            return Ok(Redirect::to("/admin/cidr_allows").into_response());
+        }
+
+        // Validated previously
+        let network = IpNetwork::from_str(&allow_form.range).unwrap_or_else(|_| unreachable!());
+
+        if let Err(e) =
+            Mutation::create_cidr_allow(&state.db, network, allow_form.reason, &user.0).await
+        {
+            if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) {
+                messages.error(format!("{network} is already allowed"));
+                return Ok(Redirect::to("/admin/cidr_allows").into_response());
+            }
+            return Err(e.into());
+        }
+
+        // Reload so users who are banned now won't be
+        state.bancache.reload().await?;
+
+        warn!(
+            "CIDR allow ({}) added by {}",
+            allow_form.range, user.0.username
+        );
+        messages.success(format!(
+            "Added CIDR allow {} successfullly",
+            allow_form.range
+        ));
+        Ok(Redirect::to("/admin/cidr_allows").into_response())
+    }
+
+    pub(super) async fn delete(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+        Form(delete_form): Form<DeleteForm>,
+    ) -> Result<Response, AppError> {
+        SessionData::check_session(&session, &delete_form.authenticity_token).await?;
+
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to delete a cidr_allow");
+            return Err(AppError::Unauthorized);
+        };
+
+        let allow = Query::find_cidr_allow(&state.db, delete_form.id)
+            .await?
+            .ok_or_else(|| AppError::NotFound)?;
+        let begin = vec_to_ipaddr(allow.range_begin)?;
+        let end = vec_to_ipaddr(allow.range_end)?;
+
+        // Delete from the database first
+        Mutation::delete_cidr_allow(&state.db, delete_form.id).await?;
+
+        // Reload the ban cache, as bans in this range apply again
+        state.bancache.reload().await?;
+        for network in find_networks(begin, end)? {
+            warn!("CIDR allow ({network}) deleted by {}", user.0.username);
+        }
+
+        messages.success(format!(
+            "Deleted CIDR allow #{} successfully",
+            delete_form.id
+        ));
+        Ok(Redirect::to("/admin/cidr_allows").into_response())
+    }
+}
+
+mod get {
+    use super::{
+        debug, warn, AppError, AppState, AuthSession, CidrAllowsTemplate, IntoResponse, Messages,
+        Query, Response, Session, SessionData, State,
+    };
+
+    pub(super) async fn cidr_allows(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+    ) -> Result<Response, AppError> {
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to access cidr_allows");
+            return Err(AppError::Unauthorized);
+        };
+
+        let authenticity_token = SessionData::new_into_session(&session).await?;
+
+        let cidr_allows = Query::fetch_all_cidr_allows(&state.db).await?;
+
+        debug!("CIDR allows retrieved by {}", user.0.username);
+
+        Ok(CidrAllowsTemplate {
+            authenticity_token: &authenticity_token,
+            messages: messages.into_iter().collect(),
+            sitename: &state.env.sitename,
+            cidr_allows,
+        }
+        .into_response())
+    }
+}
@@ -18,34 +18,41 @@ use std::str::FromStr;
 
 use askama_axum::Template;
 use axum::{
-    extract::State,
+    extract::{Query as QueryParams, State},
     response::{IntoResponse, Redirect, Response},
     routing::{get, post},
-    Form, Router,
+    Form,
 };
 use axum_messages::{Message, Messages};
 use ipnetwork::IpNetwork;
+use sea_orm::{ActiveEnum, SqlErr};
 use serde::Deserialize;
 use time::OffsetDateTime;
 use tower_sessions::Session;
 use tracing::{debug, warn};
 use validator::Validate;
 
-use entity::{cidr_ban, user};
+use entity::{cidr_ban, cidr_ban_history, sea_orm_active_enums::BanScope, user};
 use service::{Mutation, Query};
 
 use crate::{
     auth::AuthSession,
+    bancoalesce::{union_networks, CoalesceError},
+    banexport::FORMATS,
     csrf::SessionData,
     err::AppError,
     state::AppState,
     util::{
-        net::{find_networks, vec_to_ipaddr, AddressError, NetworkPrefixError},
-        string,
+        net::{find_networks, ipaddr_to_int, vec_to_ipaddr},
+        string::{self, parse_duration},
     },
-    validators::validate_network,
+    validators::{validate_duration, validate_network},
+    web::banscope::{self, SCOPES},
+    web::Routes,
 };
 
+use super::render;
+
 // CIDR ban listing page (also submission)
 #[derive(Template)]
 #[template(path = "admin/cidr_ban.html")]
@@ -56,12 +63,46 @@ struct CidrBansTemplate<'a> {
     cidr_bans: Vec<(cidr_ban::Model, Option<user::Model>)>,
 }
 
+// Shown when a new ban overlaps existing ones, to ask what to do about it
+#[derive(Template)]
+#[template(path = "admin/cidr_ban_overlap.html")]
+struct CidrBanOverlapTemplate<'a> {
+    authenticity_token: &'a str,
+    messages: Vec<Message>,
+    sitename: &'a str,
+    form: BanForm,
+    overlaps: Vec<(cidr_ban::Model, Option<user::Model>)>,
+    // What the new ban and the bans it overlaps would merge into, if they can be
+    merged: Option<Vec<IpNetwork>>,
+    // False if an overlapping ban starts or ends in the same place, so both can't be stored
+    can_keep: bool,
+}
+
+// Editing a ban, with what it was before each edit
+#[derive(Template)]
+#[template(path = "admin/cidr_ban_edit.html")]
+struct CidrBanEditTemplate<'a> {
+    authenticity_token: &'a str,
+    messages: Vec<Message>,
+    sitename: &'a str,
+    cidr_ban: cidr_ban::Model,
+    // Who created the ban
+    user: Option<user::Model>,
+    history: Vec<(cidr_ban_history::Model, Option<user::Model>)>,
+}
+
 #[derive(Debug, Clone, Deserialize, Validate)]
 struct BanForm {
     authenticity_token: String,
     #[validate(custom(function = validate_network))]
     range: String,
+    scope: String,
     reason: Option<String>,
+    // How long the ban lasts; empty for forever
+    #[validate(custom(function = validate_duration))]
+    duration: Option<String>,
+    // What to do with existing bans it overlaps: merge, replace, or keep
+    overlap: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -70,51 +111,80 @@ struct DeleteForm {
     id: i64,
 }
 
-pub fn router() -> Router<AppState> {
-    Router::new()
-        .route("/admin/cidr_bans", get(self::get::cidr_bans))
-        .route("/admin/cidr_bans", post(self::post::cidr_bans))
-        .route("/admin/cidr_bans/delete", post(self::post::delete))
-        .route("/admin/cidr_bans/flush", get(self::get::flush))
+#[derive(Debug, Clone, Deserialize)]
+struct EditQuery {
+    id: i64,
 }
 
-mod render {
-    use super::{find_networks, vec_to_ipaddr, AddressError, NetworkPrefixError};
-
-    #[derive(Debug, thiserror::Error)]
-    pub(super) enum RangeDisplayError {
-        #[error(transparent)]
-        NetworkPrefix(#[from] NetworkPrefixError),
+#[derive(Debug, Clone, Deserialize)]
+struct EditForm {
+    authenticity_token: String,
+    id: i64,
+    // Empty to leave the range be, as bans covering several networks can't be entered as one
+    range: String,
+    scope: String,
+    reason: Option<String>,
+    // How long the ban lasts from now; empty to leave the expiry be
+    duration: Option<String>,
+    // Set to make the ban permanent
+    permanent: Option<String>,
+}
 
-        #[error(transparent)]
-        Addr(#[from] AddressError),
+// Work out what a new ban and the bans it overlaps would merge into, and whether they can all be
+// kept; both ends of a range are unique, so they can't if any start or end in the same place.
+// As with coalescing, they can only be merged if they block the same things until the same time,
+// and expired bans are left out, as they'd otherwise come back to life.
+fn resolve_overlaps(
+    network: IpNetwork,
+    scope: BanScope,
+    expires_at: Option<OffsetDateTime>,
+    overlaps: &[(cidr_ban::Model, Option<user::Model>)],
+) -> Result<(Option<Vec<IpNetwork>>, bool), CoalesceError> {
+    let now = OffsetDateTime::now_utc();
+    let begin = ipaddr_to_int(network.network()).to_be_bytes();
+    let end = ipaddr_to_int(network.broadcast()).to_be_bytes();
+    let can_keep = !overlaps
+        .iter()
+        .any(|(ban, _)| ban.range_begin == begin || ban.range_end == end);
+
+    let active: Vec<&cidr_ban::Model> = overlaps
+        .iter()
+        .map(|(ban, _)| ban)
+        .filter(|ban| ban.expires_at.map_or(true, |expires_at| expires_at > now))
+        .collect();
+    if active
+        .iter()
+        .any(|ban| ban.scope != scope || ban.expires_at != expires_at)
+    {
+        return Ok((None, can_keep));
     }
 
-    // Given an IP range from the database, render it fit for display
-    // Used in templates.
-    pub(super) fn range_to_display(
-        begin: Vec<u8>,
-        end: Vec<u8>,
-    ) -> Result<Vec<String>, RangeDisplayError> {
-        if begin.len() != end.len() {
-            return Err(RangeDisplayError::NetworkPrefix(
-                NetworkPrefixError::IpTypeMismatch,
-            ));
-        }
-
-        let begin = vec_to_ipaddr(begin)?;
-        let end = vec_to_ipaddr(end)?;
+    let merged = union_networks(
+        active
+            .into_iter()
+            .map(|ban| (ban.range_begin.clone(), ban.range_end.clone()))
+            .chain([(begin.to_vec(), end.to_vec())]),
+    )?;
+    Ok((Some(merged), can_keep))
+}
 
-        let nets = find_networks(begin, end)?;
-        Ok(nets.into_iter().map(|v| format!("{v}")).collect())
-    }
+pub fn router() -> Routes {
+    Routes::new()
+        .route("/admin/cidr_bans", get(self::get::cidr_bans))
+        .route("/admin/cidr_bans", post(self::post::cidr_bans))
+        .route("/admin/cidr_bans/delete", post(self::post::delete))
+        .route("/admin/cidr_bans/edit", get(self::get::edit))
+        .route("/admin/cidr_bans/edit", post(self::post::edit))
+        .route("/admin/cidr_bans/flush", get(self::get::flush))
 }
 
 mod post {
     use super::{
-        debug, find_networks, vec_to_ipaddr, warn, AppError, AppState, AuthSession, BanForm,
-        DeleteForm, Form, FromStr, IntoResponse, IpNetwork, Messages, Mutation, Query, Redirect,
-        Response, Session, SessionData, State, Validate,
+        debug, find_networks, parse_duration, resolve_overlaps, validate_duration, vec_to_ipaddr,
+        warn, ActiveEnum, AppError, AppState, AuthSession, BanForm, BanScope,
+        CidrBanOverlapTemplate, DeleteForm, EditForm, Form, FromStr, IntoResponse, IpNetwork,
+        Messages, Mutation, OffsetDateTime, Query, Redirect, Response, Session, SessionData,
+        SqlErr, State, Validate,
     };
 
     pub(super) async fn cidr_bans(
@@ -133,15 +203,18 @@ mod post {
 
         if let Err(e) = ban_form.validate() {
             // Failed the validation checks
-            let error_reason = e
+            let (field, error_reason) = e
                 .field_errors()
-                .get("range")
-                .map_or("Unknown error".to_string(), |v| v[0].to_string());
+                .into_iter()
+                .next()
+                .map_or(("ban", "Unknown error".to_string()), |(field, v)| {
+                    (field, v[0].to_string())
+                });
             debug!(
-                "Invalid range ({}) submitted from user {}: {error_reason}",
-                ban_form.range, user.0.username
+                "Invalid {field} ({} for {:?}) submitted from user {}: {error_reason}",
+                ban_form.range, ban_form.duration, user.0.username
             );
-            messages.error(format!("Invalid range: {error_reason}").as_str());
+            messages.error(format!("Invalid {field}: {error_reason}").as_str());
             return Ok(Redirect::to("/admin/cidr_bans").into_response());
         }
 
@@ -149,12 +222,85 @@ mod post {
         let network =
             IpNetwork::from_str(&ban_form.range).map_or_else(|_| unreachable!(), |network| network);
 
-        // Invalidate so users who aren't banned will now be
-        state.bancache.invalidate(network);
+        let Ok(scope) = BanScope::try_from_value(&ban_form.scope) else {
+            warn!(
+                "Bad ban scope \"{}\" received from {}",
+                ban_form.scope, user.0.username
+            );
+            messages.error(format!("Unknown ban scope {}", ban_form.scope));
+            return Ok(Redirect::to("/admin/cidr_bans").into_response());
+        };
+
+        // Already validated; an empty duration means the ban is permanent
+        let expires_at = ban_form
+            .duration
+            .as_deref()
+            .filter(|d| !d.trim().is_empty())
+            .and_then(|d| parse_duration(d).ok())
+            .and_then(|d| OffsetDateTime::now_utc().checked_add(d));
+
+        // Bans the new one overlaps that are to be taken out, and the networks to ban instead
+        let overlaps = Query::find_overlapping_cidr_bans(&state.db, network).await?;
+        let ids: Vec<i64> = overlaps.iter().map(|(ban, _)| ban.id).collect();
+        let (remove, networks) = if overlaps.is_empty() {
+            (Vec::new(), vec![network])
+        } else {
+            let (merged, can_keep) = resolve_overlaps(network, scope, expires_at, &overlaps)?;
+            match (ban_form.overlap.as_deref(), merged) {
+                (None, merged) => {
+                    // Ask what to do about them first
+                    let authenticity_token = SessionData::new_into_session(&session).await?;
+                    return Ok(CidrBanOverlapTemplate {
+                        authenticity_token: &authenticity_token,
+                        messages: messages.into_iter().collect(),
+                        sitename: &state.env.sitename,
+                        form: ban_form,
+                        overlaps,
+                        merged,
+                        can_keep,
+                    }
+                    .into_response());
+                }
+                (Some("keep"), _) if can_keep => (Vec::new(), vec![network]),
+                (Some("merge"), Some(merged)) => (ids, merged),
+                (Some("replace"), _) => (ids, vec![network]),
+                (Some(choice), _) => {
+                    warn!(
+                        "Bad overlap choice \"{choice}\" for CIDR ban {} received from {}",
+                        ban_form.range, user.0.username
+                    );
+                    messages.error(format!("Cannot {choice} overlapping CIDR bans"));
+                    return Ok(Redirect::to("/admin/cidr_bans").into_response());
+                }
+            }
+        };
+
+        Mutation::merge_cidr_bans(
+            &state.db,
+            &remove,
+            &networks,
+            scope,
+            ban_form.reason,
+            Some(user.0.id),
+            expires_at,
+        )
+        .await?;
+        if !remove.is_empty() {
+            warn!(
+                "CIDR bans {remove:?} replaced with {networks:?} by {}",
+                user.0.username
+            );
+        }
 
-        Mutation::create_cidr_ban(&state.db, network, ban_form.reason, &user.0).await?;
+        // Reload so users who aren't banned will now be
+        state.bancache.reload().await?;
 
-        warn!("CIDR ban ({}) added by {}", ban_form.range, user.0.username);
+        warn!(
+            "CIDR ban ({}, scope {}) added by {} (expires: {expires_at:?})",
+            ban_form.range,
+            scope.to_value(),
+            user.0.username
+        );
         messages.success(format!("Added CIDR ban {} successfullly", ban_form.range));
         Ok(Redirect::to("/admin/cidr_bans").into_response())
     }
@@ -182,9 +328,9 @@ mod post {
         // Delete from the database first
         Mutation::delete_cidr_ban(&state.db, delete_form.id).await?;
 
-        // Invalidate the ban cache for all networks in this range
+        // Reload the ban cache so the ban stops applying
+        state.bancache.reload().await?;
         for network in find_networks(begin, end)? {
-            state.bancache.invalidate(network);
             warn!(
                 "CIDR ban ({}) deleted by {}",
                 network.to_string(),
@@ -195,12 +341,120 @@ mod post {
         messages.success(format!("Deleted CIDR ban #{} successfully", delete_form.id));
         Ok(Redirect::to("/admin/cidr_bans").into_response())
     }
+
+    pub(super) async fn edit(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+        Form(edit_form): Form<EditForm>,
+    ) -> Result<Response, AppError> {
+        SessionData::check_session(&session, &edit_form.authenticity_token).await?;
+
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to edit a cidr_ban");
+            return Err(AppError::Unauthorized);
+        };
+
+        let edit_page = format!("/admin/cidr_bans/edit?id={}", edit_form.id);
+        if let Some(Err(e)) = edit_form.duration.as_deref().map(validate_duration) {
+            debug!(
+                "Bad ban duration \"{:?}\" received from {} ({e})",
+                edit_form.duration, user.0.username
+            );
+            messages.error(format!("Invalid duration: {e}"));
+            return Ok(Redirect::to(&edit_page).into_response());
+        }
+
+        let network = match edit_form.range.trim() {
+            "" => None,
+            range => match IpNetwork::from_str(range) {
+                Ok(network) => Some(network),
+                Err(e) => {
+                    messages.error(format!("Invalid range: {e}"));
+                    return Ok(Redirect::to(&edit_page).into_response());
+                }
+            },
+        };
+
+        let Ok(scope) = BanScope::try_from_value(&edit_form.scope) else {
+            warn!(
+                "Bad ban scope \"{}\" received from {}",
+                edit_form.scope, user.0.username
+            );
+            messages.error(format!("Unknown ban scope {}", edit_form.scope));
+            return Ok(Redirect::to(&edit_page).into_response());
+        };
+
+        let ban = Query::find_cidr_ban(&state.db, edit_form.id)
+            .await?
+            .ok_or_else(|| AppError::NotFound)?;
+
+        // An empty duration leaves the expiry as it was
+        let expires_at = if edit_form.permanent.is_some() {
+            None
+        } else {
+            edit_form
+                .duration
+                .as_deref()
+                .filter(|d| !d.trim().is_empty())
+                .and_then(|d| parse_duration(d).ok())
+                .map_or(ban.expires_at, |d| OffsetDateTime::now_utc().checked_add(d))
+        };
+        if let Err(e) = Mutation::update_cidr_ban(
+            &state.db,
+            ban.id,
+            network,
+            scope,
+            edit_form.reason,
+            expires_at,
+            &user.0,
+        )
+        .await
+        {
+            if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) {
+                messages.error("Another CIDR ban already starts or ends there");
+                return Ok(Redirect::to(&edit_page).into_response());
+            }
+            return Err(e.into());
+        }
+
+        // Reload so the change applies
+        state.bancache.reload().await?;
+
+        warn!(
+            "CIDR ban #{} edited by {} (range {network:?}, scope {}, expires: {expires_at:?})",
+            ban.id,
+            user.0.username,
+            scope.to_value()
+        );
+        let messages = messages.success(format!("Edited CIDR ban #{} successfully", ban.id));
+
+        // A new range might now overlap other bans
+        if let Some(network) = network {
+            let overlaps: Vec<String> = Query::find_overlapping_cidr_bans(&state.db, network)
+                .await?
+                .into_iter()
+                .filter(|(overlap, _)| overlap.id != ban.id)
+                .map(|(overlap, _)| format!("#{}", overlap.id))
+                .collect();
+            if !overlaps.is_empty() {
+                messages.warning(format!(
+                    "{network} now overlaps CIDR bans {}; the coalesce-bans command can merge them",
+                    overlaps.join(", ")
+                ));
+            }
+        }
+
+        Ok(Redirect::to(&edit_page).into_response())
+    }
 }
 
 mod get {
     use super::{
-        debug, warn, AppError, AppState, AuthSession, CidrBansTemplate, IntoResponse, Messages,
-        Query, Redirect, Response, Session, SessionData, State,
+        debug, warn, AppError, AppState, AuthSession, CidrBanEditTemplate, CidrBansTemplate,
+        EditQuery, IntoResponse, Messages, Query, QueryParams, Redirect, Response, Session,
+        SessionData, State,
     };
 
     pub(super) async fn cidr_bans(
@@ -229,6 +483,45 @@ mod get {
         .into_response())
     }
 
+    pub(super) async fn edit(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+        QueryParams(edit_query): QueryParams<EditQuery>,
+    ) -> Result<Response, AppError> {
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to access cidr_ban edit");
+            return Err(AppError::Unauthorized);
+        };
+
+        let authenticity_token = SessionData::new_into_session(&session).await?;
+
+        let cidr_ban = Query::find_cidr_ban(&state.db, edit_query.id)
+            .await?
+            .ok_or_else(|| AppError::NotFound)?;
+        let creator = match cidr_ban.user_created_id {
+            Some(id) => Query::find_user_by_id(&state.db, id).await?,
+            None => None,
+        };
+        let history = Query::fetch_cidr_ban_history(&state.db, cidr_ban.id).await?;
+
+        debug!(
+            "CIDR ban #{} retrieved for editing by {}",
+            cidr_ban.id, user.0.username
+        );
+
+        Ok(CidrBanEditTemplate {
+            authenticity_token: &authenticity_token,
+            messages: messages.into_iter().collect(),
+            sitename: &state.env.sitename,
+            cidr_ban,
+            user: creator,
+            history,
+        }
+        .into_response())
+    }
+
     pub(super) async fn flush(
         auth_session: AuthSession,
         messages: Messages,
@@ -239,7 +532,8 @@ mod get {
             return Err(AppError::Unauthorized);
         };
 
-        state.bancache.invalidate_all();
+        // Picks up changes made outside the admin pages, like imports from the command line
+        state.bancache.reload().await?;
         messages.success("Flushed CIDR ban cache");
         debug!("User {} flushed ban cache", user.0.username);
         Ok(Redirect::to("/admin/cidr_bans").into_response())
@@ -0,0 +1,79 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/web/admin/export.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// Ban export route, for firewalls and proxies to fetch
+
+use axum::{
+    extract::{Query as QueryParams, State},
+    http::header,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::{
+    auth::AuthSession,
+    banexport::{self, Format},
+    err::AppError,
+    state::AppState,
+    web::Routes,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+struct ExportQuery {
+    format: Format,
+}
+
+pub fn router() -> Routes {
+    Routes::new().route("/admin/export", get(self::get::export))
+}
+
+mod get {
+    use super::{
+        banexport, debug, header, warn, AppError, AppState, AuthSession, ExportQuery, IntoResponse,
+        QueryParams, Response, State,
+    };
+
+    pub(super) async fn export(
+        auth_session: AuthSession,
+        State(state): State<AppState>,
+        QueryParams(export_query): QueryParams<ExportQuery>,
+    ) -> Result<Response, AppError> {
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to export bans");
+            return Err(AppError::Unauthorized);
+        };
+
+        let format = export_query.format;
+        let exported = banexport::export(&state.db, format).await?;
+
+        debug!("Bans exported as {} by {}", format.name(), user.0.username);
+
+        Ok((
+            [
+                (
+                    header::CONTENT_TYPE,
+                    "text/plain; charset=utf-8".to_string(),
+                ),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}\"", format.file_name()),
+                ),
+            ],
+            exported,
+        )
+            .into_response())
+    }
+}
@@ -0,0 +1,156 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/web/admin/filter_tester.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// Filter tester: see what a URL and IP would run into, without submitting anything
+
+use std::net::IpAddr;
+
+use askama_axum::Template;
+use axum::{
+    extract::{Query as QueryParams, State},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use axum_messages::{Message, Messages};
+use serde::Deserialize;
+use time::OffsetDateTime;
+use tracing::{debug, warn};
+
+use entity::{cidr_allow, cidr_ban, url_filter, user};
+use service::Query;
+
+use crate::{
+    auth::AuthSession,
+    err::AppError,
+    state::AppState,
+    urlfilter,
+    util::string,
+    validators::validate_url,
+    web::{banscope, Routes},
+};
+
+use super::render;
+
+#[derive(Template)]
+#[template(path = "admin/filter_tester.html")]
+struct FilterTesterTemplate<'a> {
+    messages: Vec<Message>,
+    sitename: &'a str,
+    url: Option<String>,
+    validation_error: Option<String>,
+    url_filters: Vec<(url_filter::Model, Option<user::Model>)>,
+    ip: Option<String>,
+    ip_error: Option<String>,
+    cidr_bans: Vec<(cidr_ban::Model, Option<user::Model>)>,
+    cidr_allows: Vec<(cidr_allow::Model, Option<user::Model>)>,
+    // What the bans in effect block, GeoIP bans included
+    banned_from: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TestForm {
+    url: Option<String>,
+    ip: Option<String>,
+}
+
+pub fn router() -> Routes {
+    Routes::new().route("/admin/filter_tester", get(self::get::filter_tester))
+}
+
+mod get {
+    use super::{
+        banscope, debug, validate_url, warn, AppError, AppState, AuthSession, FilterTesterTemplate,
+        IntoResponse, IpAddr, Messages, Query, QueryParams, Response, State, TestForm,
+    };
+
+    pub(super) async fn filter_tester(
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+        QueryParams(test_form): QueryParams<TestForm>,
+    ) -> Result<Response, AppError> {
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to access filter tester");
+            return Err(AppError::Unauthorized);
+        };
+
+        let url = test_form
+            .url
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty());
+        let ip = test_form
+            .ip
+            .map(|ip| ip.trim().to_string())
+            .filter(|ip| !ip.is_empty());
+
+        let mut validation_error = None;
+        let mut url_filters = Vec::new();
+        if let Some(url) = &url {
+            validation_error = validate_url(url, &state.url_policy)
+                .err()
+                .map(|e| e.to_string());
+
+            let matched = state.urlcache.test_url(url).await?;
+            if !matched.is_empty() {
+                url_filters = Query::fetch_all_url_filters(&state.db)
+                    .await?
+                    .into_iter()
+                    .filter(|(url_filter, _)| matched.contains(&url_filter.id))
+                    .collect();
+            }
+        }
+
+        let mut ip_error = None;
+        let mut cidr_bans = Vec::new();
+        let mut cidr_allows = Vec::new();
+        let mut banned_from = Vec::new();
+        if let Some(ip) = &ip {
+            match ip.parse::<IpAddr>() {
+                Ok(addr) => {
+                    let addr = addr.to_canonical();
+                    cidr_bans = Query::find_ip_bans(&state.db, addr).await?;
+                    cidr_allows = Query::find_ip_allows(&state.db, addr).await?;
+                    // The ban cache knows about GeoIP bans as well
+                    let bans = state.bancache.check_ban(addr).await?;
+                    for (_, scope) in bans.cidr.iter().chain(&bans.geo) {
+                        let scope = banscope::describe(*scope);
+                        if !banned_from.contains(&scope) {
+                            banned_from.push(scope);
+                        }
+                    }
+                }
+                Err(e) => ip_error = Some(format!("Invalid IP address: {e}")),
+            }
+        }
+
+        debug!(
+            "Filter tester used by {} (URL: {url:?}, IP: {ip:?})",
+            user.0.username
+        );
+
+        Ok(FilterTesterTemplate {
+            messages: messages.into_iter().collect(),
+            sitename: &state.env.sitename,
+            url,
+            validation_error,
+            url_filters,
+            ip,
+            ip_error,
+            cidr_bans,
+            cidr_allows,
+            banned_from,
+        }
+        .into_response())
+    }
+}
@@ -0,0 +1,275 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/web/admin/geo_ban.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// GeoIP ban routes, for banning by country or autonomous system
+
+use askama_axum::Template;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form,
+};
+use axum_messages::{Message, Messages};
+use sea_orm::{ActiveEnum, SqlErr};
+use serde::Deserialize;
+use time::OffsetDateTime;
+use tower_sessions::Session;
+use tracing::{debug, warn};
+
+use entity::{
+    geo_ban,
+    sea_orm_active_enums::{BanScope, GeoKind},
+    user,
+};
+use service::{Mutation, Query};
+
+use crate::{
+    auth::AuthSession,
+    csrf::SessionData,
+    err::AppError,
+    geoip::{self, KINDS},
+    state::AppState,
+    util::string::{self, parse_duration},
+    validators::validate_duration,
+    web::banscope::{self, SCOPES},
+    web::Routes,
+};
+
+// GeoIP ban listing page (also submission)
+#[derive(Template)]
+#[template(path = "admin/geo_ban.html")]
+struct GeoBansTemplate<'a> {
+    authenticity_token: &'a str,
+    messages: Vec<Message>,
+    sitename: &'a str,
+    // The databases that are open, if any
+    databases: Vec<(GeoKind, String)>,
+    geo_bans: Vec<(geo_ban::Model, Option<user::Model>)>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct BanForm {
+    authenticity_token: String,
+    kind: String,
+    value: String,
+    scope: String,
+    reason: Option<String>,
+    // How long the ban lasts; empty for forever
+    duration: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DeleteForm {
+    authenticity_token: String,
+    id: i64,
+}
+
+pub fn router() -> Routes {
+    Routes::new()
+        .route("/admin/geo_bans", get(self::get::geo_bans))
+        .route("/admin/geo_bans", post(self::post::geo_bans))
+        .route("/admin/geo_bans/delete", post(self::post::delete))
+        .route("/admin/geo_bans/reload", get(self::get::reload))
+}
+
+mod post {
+    use super::{
+        debug, geoip, parse_duration, validate_duration, warn, ActiveEnum, AppError, AppState,
+        AuthSession, BanForm, BanScope, DeleteForm, Form, GeoKind, IntoResponse, Messages,
+        Mutation, OffsetDateTime, Query, Redirect, Response, Session, SessionData, SqlErr, State,
+    };
+
+    pub(super) async fn geo_bans(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+        Form(ban_form): Form<BanForm>,
+    ) -> Result<Response, AppError> {
+        SessionData::check_session(&session, &ban_form.authenticity_token).await?;
+
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to add a geo_ban");
+            return Err(AppError::Unauthorized);
+        };
+
+        let (Ok(kind), Ok(scope)) = (
+            GeoKind::try_from_value(&ban_form.kind),
+            BanScope::try_from_value(&ban_form.scope),
+        ) else {
+            warn!(
+                "Bad GeoIP ban kind \"{}\" or scope \"{}\" received from {}",
+                ban_form.kind, ban_form.scope, user.0.username
+            );
+            messages.error(format!(
+                "Unknown GeoIP ban kind {} or scope {}",
+                ban_form.kind, ban_form.scope
+            ));
+            return Ok(Redirect::to("/admin/geo_bans").into_response());
+        };
+
+        let value = match geoip::normalize(kind, &ban_form.value) {
+            Err(e) => {
+                debug!(
+                    "Bad GeoIP ban \"{}\" received from {} ({e})",
+                    ban_form.value, user.0.username
+                );
+                messages.error(e.to_string());
+                return Ok(Redirect::to("/admin/geo_bans").into_response());
+            }
+            Ok(value) => value,
+        };
+
+        if let Some(Err(e)) = ban_form.duration.as_deref().map(validate_duration) {
+            debug!(
+                "Bad GeoIP ban duration \"{:?}\" received from {} ({e})",
+                ban_form.duration, user.0.username
+            );
+            messages.error(format!("Invalid duration: {e}"));
+            return Ok(Redirect::to("/admin/geo_bans").into_response());
+        }
+
+        // An empty duration means the ban is permanent
+        let expires_at = ban_form
+            .duration
+            .as_deref()
+            .filter(|d| !d.trim().is_empty())
+            .and_then(|d| parse_duration(d).ok())
+            .and_then(|d| OffsetDateTime::now_utc().checked_add(d));
+
+        let name = geoip::display(kind, &value);
+        if let Err(e) = Mutation::create_geo_ban(
+            &state.db,
+            kind,
+            value,
+            scope,
+            ban_form.reason,
+            &user.0,
+            expires_at,
+        )
+        .await
+        {
+            if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) {
+                messages.error(format!("{name} already has a ban with that scope"));
+                return Ok(Redirect::to("/admin/geo_bans").into_response());
+            }
+            return Err(e.into());
+        }
+
+        // Reload so users who aren't banned will now be
+        state.bancache.reload().await?;
+
+        warn!(
+            "GeoIP ban ({name}, scope {}) added by {} (expires: {expires_at:?})",
+            scope.to_value(),
+            user.0.username
+        );
+        let messages = messages.success(format!("Added GeoIP ban {name} successfully"));
+        if !state.geoip.is_enabled() {
+            messages.error("No GeoIP databases are configured, so this ban won't match anything");
+        }
+        Ok(Redirect::to("/admin/geo_bans").into_response())
+    }
+
+    pub(super) async fn delete(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+        Form(delete_form): Form<DeleteForm>,
+    ) -> Result<Response, AppError> {
+        SessionData::check_session(&session, &delete_form.authenticity_token).await?;
+
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to delete a geo_ban");
+            return Err(AppError::Unauthorized);
+        };
+
+        let ban = Query::find_geo_ban(&state.db, delete_form.id)
+            .await?
+            .ok_or_else(|| AppError::NotFound)?;
+
+        Mutation::delete_geo_ban(&state.db, delete_form.id).await?;
+
+        // Reload the ban cache so the ban stops applying
+        state.bancache.reload().await?;
+        warn!(
+            "GeoIP ban ({}) deleted by {}",
+            geoip::display(ban.kind, &ban.value),
+            user.0.username
+        );
+
+        messages.success(format!(
+            "Deleted GeoIP ban #{} successfully",
+            delete_form.id
+        ));
+        Ok(Redirect::to("/admin/geo_bans").into_response())
+    }
+}
+
+mod get {
+    use super::{
+        debug, warn, AppError, AppState, AuthSession, GeoBansTemplate, IntoResponse, Messages,
+        Query, Redirect, Response, Session, SessionData, State,
+    };
+
+    pub(super) async fn geo_bans(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+    ) -> Result<Response, AppError> {
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to access geo_bans");
+            return Err(AppError::Unauthorized);
+        };
+
+        let authenticity_token = SessionData::new_into_session(&session).await?;
+
+        let geo_bans = Query::fetch_all_geo_bans(&state.db).await?;
+
+        debug!("GeoIP bans retrieved by {}", user.0.username);
+
+        Ok(GeoBansTemplate {
+            authenticity_token: &authenticity_token,
+            messages: messages.into_iter().collect(),
+            sitename: &state.env.sitename,
+            databases: state.geoip.describe().await,
+            geo_bans,
+        }
+        .into_response())
+    }
+
+    pub(super) async fn reload(
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+    ) -> Result<Response, AppError> {
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to reload GeoIP databases");
+            return Err(AppError::Unauthorized);
+        };
+
+        // A database that fails to open leaves the old ones in place, so this isn't fatal
+        if let Err(e) = state.geoip.reload().await {
+            warn!("Reloading GeoIP databases failed: {e}");
+            messages.error(format!("Could not reload GeoIP databases: {e}"));
+        } else {
+            messages.success("Reloaded GeoIP databases");
+        }
+        debug!("User {} reloaded GeoIP databases", user.0.username);
+        Ok(Redirect::to("/admin/geo_bans").into_response())
+    }
+}
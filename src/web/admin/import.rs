@@ -0,0 +1,218 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/web/admin/import.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// Blocklist import routes
+
+use askama_axum::Template;
+use axum::{
+    extract::{
+        multipart::{Multipart, MultipartError},
+        DefaultBodyLimit, State,
+    },
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+};
+use axum_messages::{Message, Messages};
+use tower_sessions::Session;
+use tracing::{debug, warn};
+
+use crate::{
+    auth::AuthSession,
+    blocklist::{self, BlocklistError, FORMATS},
+    csrf::SessionData,
+    err::AppError,
+    state::AppState,
+    web::Routes,
+};
+
+// Blocklist import page
+#[derive(Template)]
+#[template(path = "admin/import.html")]
+struct ImportTemplate<'a> {
+    authenticity_token: &'a str,
+    messages: Vec<Message>,
+    sitename: &'a str,
+}
+
+// Largest blocklist that can be uploaded; real lists run well past the usual limit on bodies
+const IMPORT_BODY_LIMIT: usize = 64 * 1024 * 1024;
+
+// How many invalid lines are listed after an import before the rest are only counted
+const INVALID_LINES_SHOWN: usize = 20;
+
+#[derive(Debug, Clone, Default)]
+struct ImportForm {
+    authenticity_token: String,
+    format: String,
+    source: String,
+    // The blocklist itself, uploaded or pasted in
+    list: String,
+}
+
+impl ImportForm {
+    // Read the form, taking the list from the uploaded file if there is one, or else what was
+    // pasted in
+    async fn read(mut multipart: Multipart) -> Result<Self, MultipartError> {
+        let mut form = Self::default();
+        let mut pasted = String::new();
+        while let Some(field) = multipart.next_field().await? {
+            let name = field.name().map(ToString::to_string);
+            match name.as_deref() {
+                Some("authenticity_token") => form.authenticity_token = field.text().await?,
+                Some("format") => form.format = field.text().await?,
+                Some("source") => form.source = field.text().await?,
+                Some("file") => form.list = field.text().await?,
+                Some("list") => pasted = field.text().await?,
+                _ => (),
+            }
+        }
+
+        // Browsers send an empty file when none was chosen
+        if form.list.trim().is_empty() {
+            form.list = pasted;
+        }
+        Ok(form)
+    }
+}
+
+pub fn router() -> Routes {
+    Routes::new()
+        .route("/admin/import", get(self::get::import))
+        .route(
+            "/admin/import",
+            post(self::post::import).layer(DefaultBodyLimit::max(IMPORT_BODY_LIMIT)),
+        )
+}
+
+mod post {
+    use super::{
+        blocklist, debug, warn, AppError, AppState, AuthSession, BlocklistError, ImportForm,
+        IntoResponse, Messages, Multipart, Redirect, Response, Session, SessionData, State,
+        FORMATS, INVALID_LINES_SHOWN,
+    };
+
+    pub(super) async fn import(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(mut state): State<AppState>,
+        multipart: Multipart,
+    ) -> Result<Response, AppError> {
+        let import_form = ImportForm::read(multipart).await?;
+        SessionData::check_session(&session, &import_form.authenticity_token).await?;
+
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to import a blocklist");
+            return Err(AppError::Unauthorized);
+        };
+
+        let Some(format) = FORMATS
+            .into_iter()
+            .find(|format| format.name() == import_form.format)
+        else {
+            warn!(
+                "Bad blocklist format \"{}\" received from {}",
+                import_form.format, user.0.username
+            );
+            messages.error(format!("Unknown blocklist format {}", import_form.format));
+            return Ok(Redirect::to("/admin/import").into_response());
+        };
+
+        let report = match blocklist::import(
+            &state.db,
+            format,
+            &import_form.source,
+            &import_form.list,
+            &user.0,
+        )
+        .await
+        {
+            Err(BlocklistError::Db(e)) => return Err(e.into()),
+            Err(e) => {
+                debug!(
+                    "Bad blocklist import \"{}\" from {} ({e})",
+                    import_form.source, user.0.username
+                );
+                messages.error(format!("Could not import blocklist: {e}"));
+                return Ok(Redirect::to("/admin/import").into_response());
+            }
+            Ok(report) => report,
+        };
+
+        // Imports replace entries wholesale, so start over
+        state.urlcache.sync_filter_cache().await?;
+        state.bancache.reload().await?;
+
+        warn!(
+            "Blocklist {} ({}) imported by {}: {:?}",
+            import_form.source,
+            format.name(),
+            user.0.username,
+            report.result
+        );
+        let messages = messages.success(format!(
+            "Imported {}: {} added, {} removed, {} already present",
+            import_form.source.trim(),
+            report.result.added,
+            report.result.removed,
+            report.result.skipped
+        ));
+        if !report.invalid.is_empty() {
+            let lines: Vec<_> = report
+                .invalid
+                .iter()
+                .take(INVALID_LINES_SHOWN)
+                .map(ToString::to_string)
+                .collect();
+            let more = report.invalid.len().saturating_sub(INVALID_LINES_SHOWN);
+            if more > 0 {
+                messages.error(format!(
+                    "Skipped invalid lines: {}, and {more} more",
+                    lines.join(", ")
+                ));
+            } else {
+                messages.error(format!("Skipped invalid lines: {}", lines.join(", ")));
+            }
+        }
+        Ok(Redirect::to("/admin/import").into_response())
+    }
+}
+
+mod get {
+    use super::{
+        warn, AppError, AppState, AuthSession, ImportTemplate, IntoResponse, Messages, Response,
+        Session, SessionData, State,
+    };
+
+    pub(super) async fn import(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+    ) -> Result<Response, AppError> {
+        if auth_session.user.is_none() {
+            warn!("Unauthorized attempt to access blocklist import");
+            return Err(AppError::Unauthorized);
+        }
+
+        let authenticity_token = SessionData::new_into_session(&session).await?;
+
+        Ok(ImportTemplate {
+            authenticity_token: &authenticity_token,
+            messages: messages.into_iter().collect(),
+            sitename: &state.env.sitename,
+        }
+        .into_response())
+    }
+}
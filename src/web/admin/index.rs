@@ -17,12 +17,11 @@ use axum::{
     extract::State,
     response::{IntoResponse, Response},
     routing::get,
-    Router,
 };
 use axum_messages::{Message, Messages};
 use tracing::{debug, warn};
 
-use crate::{auth::AuthSession, err::AppError, state::AppState};
+use crate::{auth::AuthSession, err::AppError, state::AppState, web::Routes};
 
 #[derive(Template)]
 #[template(path = "admin/index.html")]
@@ -31,8 +30,8 @@ struct IndexTemplate<'a> {
     sitename: &'a str,
 }
 
-pub fn router() -> Router<AppState> {
-    Router::new().route("/admin", get(self::get::index))
+pub fn router() -> Routes {
+    Routes::new().route("/admin", get(self::get::index))
 }
 
 mod get {
@@ -0,0 +1,324 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/web/admin/ip.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// IP investigation: everything we know about an IP, and what can be done about it
+
+use std::net::IpAddr;
+
+use askama_axum::Template;
+use axum::{
+    extract::{Query as QueryParams, State},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form,
+};
+use axum_messages::{Message, Messages};
+use ipnetwork::{IpNetwork, Ipv4Network, Ipv6Network};
+use sea_orm::ActiveEnum;
+use serde::Deserialize;
+use time::OffsetDateTime;
+use tower_sessions::Session;
+use tracing::{debug, warn};
+
+use entity::{cidr_allow, cidr_ban, geo_ban, sea_orm_active_enums::GeoKind, url, user};
+use service::{Mutation, Query};
+
+use crate::{
+    auth::AuthSession,
+    bancache::Bans,
+    csrf::SessionData,
+    err::AppError,
+    geoip::{self, Location},
+    state::AppState,
+    util::string,
+    web::banscope::{self, SCOPES},
+    web::Routes,
+};
+
+use super::render;
+
+// Prefixes offered for one-click bans; an IPv6 /64 usually belongs to one client
+const IPV4_PREFIX: u8 = 24;
+const IPV6_PREFIX: u8 = 64;
+
+#[derive(Template)]
+#[template(path = "admin/ip.html")]
+struct IpTemplate<'a> {
+    authenticity_token: &'a str,
+    messages: Vec<Message>,
+    sitename: &'a str,
+    ip: Option<String>,
+    ip_error: Option<String>,
+    // The network a one-click ban covers; only set for a valid IP
+    network: Option<IpNetwork>,
+    location: Location,
+    cidr_bans: Vec<(cidr_ban::Model, Option<user::Model>)>,
+    cidr_allows: Vec<(cidr_allow::Model, Option<user::Model>)>,
+    geo_bans: Vec<(geo_ban::Model, Option<user::Model>)>,
+    // What the ban cache has in effect for the IP
+    cached: Bans,
+    urls: Vec<url::Model>,
+    // The network truncated submitter addresses are stored under, and the other URL's stored under
+    // it, which could have come from anyone there
+    stored_network: Option<IpNetwork>,
+    network_urls: Vec<url::Model>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct IpQuery {
+    ip: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct IpForm {
+    authenticity_token: String,
+    ip: String,
+}
+
+pub fn router() -> Routes {
+    Routes::new()
+        .route("/admin/ip", get(self::get::ip))
+        .route("/admin/ip/purge", post(self::post::purge))
+        .route("/admin/ip/disable", post(self::post::disable))
+        .route(
+            "/admin/ip/disable_network",
+            post(self::post::disable_network),
+        )
+}
+
+// The network around an IP a one-click ban covers
+fn ban_network(addr: IpAddr) -> IpNetwork {
+    // Should not fail, as the prefixes are in range
+    match addr.to_canonical() {
+        IpAddr::V4(addr) => {
+            let network = Ipv4Network::new(addr, IPV4_PREFIX).expect("Invalid IPv4 prefix");
+            IpNetwork::V4(
+                Ipv4Network::new(network.network(), IPV4_PREFIX).expect("Invalid IPv4 prefix"),
+            )
+        }
+        IpAddr::V6(addr) => {
+            let network = Ipv6Network::new(addr, IPV6_PREFIX).expect("Invalid IPv6 prefix");
+            IpNetwork::V6(
+                Ipv6Network::new(network.network(), IPV6_PREFIX).expect("Invalid IPv6 prefix"),
+            )
+        }
+    }
+}
+
+// True if a GeoIP ban applies to where an IP is
+fn geo_ban_matches(ban: &geo_ban::Model, location: &Location) -> bool {
+    match ban.kind {
+        GeoKind::Country => location.country.as_deref() == Some(ban.value.as_str()),
+        GeoKind::Asn => location.asn.is_some_and(|asn| asn.to_string() == ban.value),
+    }
+}
+
+// Where to go back to after acting on an IP
+fn ip_page(addr: IpAddr) -> Redirect {
+    Redirect::to(&format!("/admin/ip?ip={addr}"))
+}
+
+mod post {
+    use super::{
+        ip_page, warn, AppError, AppState, AuthSession, Form, IntoResponse, IpAddr, IpForm,
+        Messages, Mutation, Query, Redirect, Response, Session, SessionData, State,
+    };
+
+    pub(super) async fn purge(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+        Form(ip_form): Form<IpForm>,
+    ) -> Result<Response, AppError> {
+        SessionData::check_session(&session, &ip_form.authenticity_token).await?;
+
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to purge cached IP state");
+            return Err(AppError::Unauthorized);
+        };
+
+        let Ok(addr) = ip_form.ip.trim().parse::<IpAddr>() else {
+            messages.error(format!("Invalid IP address {}", ip_form.ip));
+            return Ok(Redirect::to("/admin/ip").into_response());
+        };
+
+        // The ban index is shared by every IP, so it's rebuilt from the database
+        state.autoban.forget(addr).await;
+        state.bancache.reload().await?;
+
+        warn!("Cached state for {addr} purged by {}", user.0.username);
+        messages.success(format!("Purged cached state for {addr}"));
+        Ok(ip_page(addr).into_response())
+    }
+
+    pub(super) async fn disable(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+        Form(ip_form): Form<IpForm>,
+    ) -> Result<Response, AppError> {
+        SessionData::check_session(&session, &ip_form.authenticity_token).await?;
+
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to disable URL's by IP");
+            return Err(AppError::Unauthorized);
+        };
+
+        let Ok(addr) = ip_form.ip.trim().parse::<IpAddr>() else {
+            messages.error(format!("Invalid IP address {}", ip_form.ip));
+            return Ok(Redirect::to("/admin/ip").into_response());
+        };
+
+        let submitter = state.ip_storage.submitter(addr);
+        let ids: Vec<i64> =
+            Query::find_urls_by_submitter(&state.db, &submitter.ips, submitter.ip_hmac)
+                .await?
+                .into_iter()
+                .map(|url| url.id)
+                .collect();
+        let affected = Mutation::disable_urls(&state.db, &ids).await?.rows_affected;
+
+        warn!(
+            "{affected} URL's submitted by {addr} disabled by {}",
+            user.0.username
+        );
+        messages.success(format!("Disabled {affected} URL's submitted by {addr}"));
+        Ok(ip_page(addr).into_response())
+    }
+
+    pub(super) async fn disable_network(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+        Form(ip_form): Form<IpForm>,
+    ) -> Result<Response, AppError> {
+        SessionData::check_session(&session, &ip_form.authenticity_token).await?;
+
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to disable URL's by network");
+            return Err(AppError::Unauthorized);
+        };
+
+        let Ok(addr) = ip_form.ip.trim().parse::<IpAddr>() else {
+            messages.error(format!("Invalid IP address {}", ip_form.ip));
+            return Ok(Redirect::to("/admin/ip").into_response());
+        };
+
+        let Some(network) = state.ip_storage.submitter(addr).network else {
+            messages.error("Submitter IP's aren't stored truncated to their network");
+            return Ok(ip_page(addr).into_response());
+        };
+
+        let ids: Vec<i64> = Query::find_urls_by_submitter(&state.db, &[network.network()], None)
+            .await?
+            .into_iter()
+            .map(|url| url.id)
+            .collect();
+        let affected = Mutation::disable_urls(&state.db, &ids).await?.rows_affected;
+
+        warn!(
+            "{affected} URL's stored under {network} disabled by {}",
+            user.0.username
+        );
+        messages.success(format!(
+            "Disabled {affected} URL's submitted by anyone in {network}"
+        ));
+        Ok(ip_page(addr).into_response())
+    }
+}
+
+mod get {
+    use super::{
+        ban_network, debug, geo_ban_matches, warn, AppError, AppState, AuthSession, Bans,
+        IntoResponse, IpAddr, IpQuery, IpTemplate, Location, Messages, Query, QueryParams,
+        Response, Session, SessionData, State,
+    };
+
+    pub(super) async fn ip(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+        QueryParams(ip_query): QueryParams<IpQuery>,
+    ) -> Result<Response, AppError> {
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to investigate an IP");
+            return Err(AppError::Unauthorized);
+        };
+
+        let authenticity_token = SessionData::new_into_session(&session).await?;
+
+        let ip = ip_query
+            .ip
+            .map(|ip| ip.trim().to_string())
+            .filter(|ip| !ip.is_empty());
+
+        let mut template = IpTemplate {
+            authenticity_token: &authenticity_token,
+            messages: messages.into_iter().collect(),
+            sitename: &state.env.sitename,
+            ip: ip.clone(),
+            ip_error: None,
+            network: None,
+            location: Location::default(),
+            cidr_bans: Vec::new(),
+            cidr_allows: Vec::new(),
+            geo_bans: Vec::new(),
+            cached: Bans::default(),
+            urls: Vec::new(),
+            stored_network: None,
+            network_urls: Vec::new(),
+        };
+
+        if let Some(ip) = &ip {
+            match ip.parse::<IpAddr>() {
+                Ok(addr) => {
+                    let addr = addr.to_canonical();
+                    let location = state.geoip.lookup(addr).await;
+                    template.network = Some(ban_network(addr));
+                    template.cidr_bans = Query::find_ip_bans(&state.db, addr).await?;
+                    template.cidr_allows = Query::find_ip_allows(&state.db, addr).await?;
+                    template.geo_bans = Query::fetch_all_geo_bans(&state.db)
+                        .await?
+                        .into_iter()
+                        .filter(|(ban, _)| geo_ban_matches(ban, &location))
+                        .collect();
+                    template.cached = state.bancache.check_ban(addr).await?;
+                    let submitter = state.ip_storage.submitter(addr);
+                    template.urls =
+                        Query::find_urls_by_submitter(&state.db, &submitter.ips, submitter.ip_hmac)
+                            .await?;
+                    if let Some(network) = submitter.network {
+                        template.network_urls =
+                            Query::find_urls_by_submitter(&state.db, &[network.network()], None)
+                                .await?
+                                .into_iter()
+                                .filter(|url| !template.urls.iter().any(|u| u.id == url.id))
+                                .collect();
+                        template.stored_network = Some(network);
+                    }
+                    template.location = location;
+                }
+                Err(e) => template.ip_error = Some(format!("Invalid IP address: {e}")),
+            }
+        }
+
+        debug!("IP {ip:?} investigated by {}", user.0.username);
+
+        Ok(template.into_response())
+    }
+}
@@ -0,0 +1,48 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/web/admin/render.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// Helpers for rendering things in admin templates
+
+use crate::util::net::{find_networks, vec_to_ipaddr, AddressError, NetworkPrefixError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum RangeDisplayError {
+    #[error(transparent)]
+    NetworkPrefix(#[from] NetworkPrefixError),
+
+    #[error(transparent)]
+    Addr(#[from] AddressError),
+}
+
+// Given an IP from the database, render it fit for display
+// Used in templates.
+pub fn ip_to_display(addr: Vec<u8>) -> Result<String, AddressError> {
+    Ok(vec_to_ipaddr(addr)?.to_string())
+}
+
+// Given an IP range from the database, render it fit for display
+// Used in templates.
+pub fn range_to_display(begin: Vec<u8>, end: Vec<u8>) -> Result<Vec<String>, RangeDisplayError> {
+    if begin.len() != end.len() {
+        return Err(RangeDisplayError::NetworkPrefix(
+            NetworkPrefixError::IpTypeMismatch,
+        ));
+    }
+
+    let begin = vec_to_ipaddr(begin)?;
+    let end = vec_to_ipaddr(end)?;
+
+    let nets = find_networks(begin, end)?;
+    Ok(nets.into_iter().map(|v| format!("{v}")).collect())
+}
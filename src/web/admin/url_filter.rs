@@ -16,22 +16,36 @@
 
 use askama_axum::Template;
 use axum::{
-    extract::State,
+    extract::{Query as QueryParams, State},
     response::{IntoResponse, Redirect, Response},
     routing::{get, post},
-    Form, Router,
+    Form,
 };
 use axum_messages::{Message, Messages};
-use regex::Regex;
+use sea_orm::{ActiveEnum, SqlErr, TryIntoModel};
 use serde::Deserialize;
 use time::OffsetDateTime;
 use tower_sessions::Session;
 use tracing::{debug, warn};
 
-use entity::{url_filter, user};
+use entity::{sea_orm_active_enums::FilterKind, url, url_filter, url_filter_history, user};
 use service::{Mutation, Query};
 
-use crate::{auth::AuthSession, csrf::SessionData, err::AppError, state::AppState, util::string};
+use crate::{
+    auth::AuthSession,
+    csrf::SessionData,
+    err::AppError,
+    state::AppState,
+    urlfilter::{self, Matcher, Target, KINDS},
+    util::string::{self, parse_duration},
+    validators::validate_duration,
+    web::Routes,
+};
+
+use super::render;
+
+// How many URL's to go through at a time when looking for matches
+const SCAN_BATCH: u64 = 1000;
 
 // URL filter landing page (also submission page)
 #[derive(Template)]
@@ -43,11 +57,40 @@ struct UrlFiltersTemplate<'a> {
     url_filters: Vec<(url_filter::Model, Option<user::Model>)>,
 }
 
+// Existing URL's matching a filter, to deal with them after the fact
+#[derive(Template)]
+#[template(path = "admin/url_filter_matches.html")]
+struct UrlFilterMatchesTemplate<'a> {
+    authenticity_token: &'a str,
+    messages: Vec<Message>,
+    sitename: &'a str,
+    url_filter: url_filter::Model,
+    urls: Vec<url::Model>,
+}
+
+// Editing a filter, with what it was before each edit
+#[derive(Template)]
+#[template(path = "admin/url_filter_edit.html")]
+struct UrlFilterEditTemplate<'a> {
+    authenticity_token: &'a str,
+    messages: Vec<Message>,
+    sitename: &'a str,
+    url_filter: url_filter::Model,
+    // Who created the filter
+    user: Option<user::Model>,
+    history: Vec<(url_filter_history::Model, Option<user::Model>)>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 struct FilterForm {
     authenticity_token: String,
     filter: String,
+    kind: String,
     reason: Option<String>,
+    // How long the filter lasts; empty for forever
+    duration: Option<String>,
+    // Set to look for existing URL's matching the filter afterwards
+    scan: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -56,18 +99,86 @@ struct DeleteForm {
     id: i64,
 }
 
-pub fn router() -> Router<AppState> {
-    Router::new()
+#[derive(Debug, Clone, Deserialize)]
+struct MatchesQuery {
+    id: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct EditQuery {
+    id: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct EditForm {
+    authenticity_token: String,
+    id: i64,
+    filter: String,
+    kind: String,
+    reason: Option<String>,
+    // How long the filter lasts from now; empty to leave the expiry be
+    duration: Option<String>,
+    // Set to make the filter permanent
+    permanent: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum BulkAction {
+    Disable,
+    Delete,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct BulkForm {
+    authenticity_token: String,
+    id: i64,
+    action: BulkAction,
+}
+
+pub fn router() -> Routes {
+    Routes::new()
         .route("/admin/url_filters", get(self::get::url_filters))
         .route("/admin/url_filters", post(self::post::url_filters))
         .route("/admin/url_filters/delete", post(self::post::delete))
+        .route("/admin/url_filters/edit", get(self::get::edit))
+        .route("/admin/url_filters/edit", post(self::post::edit))
         .route("/admin/url_filters/flush", get(self::get::flush))
+        .route("/admin/url_filters/matches", get(self::get::matches))
+        .route("/admin/url_filters/matches", post(self::post::matches))
+}
+
+// Find every existing URL a filter matches.
+// URL's are gone through a batch at a time, so only the matches are kept in memory.
+async fn matching_urls(
+    state: &AppState,
+    url_filter: &url_filter::Model,
+) -> Result<Vec<url::Model>, AppError> {
+    let matcher = Matcher::new(url_filter.kind, &url_filter.filter)?;
+    let mut urls = Vec::new();
+    let mut after = 0;
+    loop {
+        let batch = Query::fetch_urls_after(&state.db, after, SCAN_BATCH).await?;
+        let Some(last) = batch.last() else {
+            break;
+        };
+        after = last.id;
+        urls.extend(
+            batch
+                .into_iter()
+                .filter(|url| matcher.is_match(&Target::new(&url.url))),
+        );
+    }
+
+    Ok(urls)
 }
 
 mod post {
     use super::{
-        debug, warn, AppError, AppState, AuthSession, DeleteForm, FilterForm, Form, IntoResponse,
-        Messages, Mutation, Query, Redirect, Regex, Response, Session, SessionData, State,
+        debug, matching_urls, parse_duration, urlfilter, validate_duration, warn, ActiveEnum,
+        AppError, AppState, AuthSession, BulkAction, BulkForm, DeleteForm, EditForm, FilterForm,
+        FilterKind, Form, IntoResponse, Messages, Mutation, OffsetDateTime, Query, Redirect,
+        Response, Session, SessionData, SqlErr, State, TryIntoModel,
     };
 
     pub(super) async fn url_filters(
@@ -84,41 +195,77 @@ mod post {
             return Err(AppError::Unauthorized);
         };
 
-        if filter_form.filter.is_empty() {
-            debug!("Empty filter received from {}", user.0.username);
-            messages.error("Filter cannot be empty");
+        let Ok(kind) = FilterKind::try_from_value(&filter_form.kind) else {
+            debug!(
+                "Bad filter kind \"{}\" received from {}",
+                filter_form.kind, user.0.username
+            );
+            messages.error(format!("Unknown URL filter kind {}", filter_form.kind));
             return Ok(Redirect::to("/admin/url_filters").into_response());
-        }
+        };
 
-        let cmpreg = match Regex::new(&filter_form.filter) {
+        let filter = match urlfilter::normalize(kind, &filter_form.filter) {
             Err(e) => {
                 debug!(
-                    "Bad filter regex \"{}\" received from {} ({e})",
+                    "Bad filter \"{}\" received from {} ({e})",
                     filter_form.filter, user.0.username
                 );
-                messages.error(format!(
-                    "Malformed URL filter regex {}: {e}",
-                    filter_form.filter
-                ));
+                messages.error(format!("Malformed URL filter {}: {e}", filter_form.filter));
                 return Ok(Redirect::to("/admin/url_filters").into_response());
             }
-            Ok(cmpreg) => cmpreg,
+            Ok(filter) => filter,
         };
 
-        Mutation::create_url_filter(
+        if let Some(Err(e)) = filter_form.duration.as_deref().map(validate_duration) {
+            debug!(
+                "Bad filter duration \"{:?}\" received from {} ({e})",
+                filter_form.duration, user.0.username
+            );
+            messages.error(format!("Invalid duration: {e}"));
+            return Ok(Redirect::to("/admin/url_filters").into_response());
+        }
+
+        // An empty duration means the filter is permanent
+        let expires_at = filter_form
+            .duration
+            .as_deref()
+            .filter(|d| !d.trim().is_empty())
+            .and_then(|d| parse_duration(d).ok())
+            .and_then(|d| OffsetDateTime::now_utc().checked_add(d));
+
+        let url_filter = match Mutation::create_url_filter(
             &state.db,
-            filter_form.filter.clone(),
+            filter.clone(),
+            kind,
             filter_form.reason,
             &user.0,
+            expires_at,
         )
-        .await?;
-        state.urlcache.add_regex_cache(cmpreg).await?;
+        .await
+        {
+            Ok(url_filter) => url_filter.try_into_model()?,
+            Err(e) if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => {
+                messages.error(format!(
+                    "There is already a URL filter {filter} ({})",
+                    urlfilter::describe(kind)
+                ));
+                return Ok(Redirect::to("/admin/url_filters").into_response());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let id = url_filter.id;
+        state.urlcache.add_filter_cache(url_filter).await?;
 
         warn!(
-            "URL filter created by {}: {}",
-            user.0.username, filter_form.filter
+            "URL filter created by {}: {} ({}, expires: {expires_at:?})",
+            user.0.username,
+            filter,
+            kind.to_value()
         );
-        messages.success(format!("Added filter {} successfullly", filter_form.filter));
+        messages.success(format!("Added filter {filter} successfullly"));
+        if filter_form.scan.is_some() {
+            return Ok(Redirect::to(&format!("/admin/url_filters/matches?id={id}")).into_response());
+        }
         Ok(Redirect::to("/admin/url_filters").into_response())
     }
 
@@ -141,7 +288,7 @@ mod post {
             .ok_or_else(|| AppError::NotFound)?;
 
         Mutation::delete_url_filter(&state.db, delete_form.id).await?;
-        state.urlcache.remove_regex_cache(&filter.filter).await?;
+        state.urlcache.remove_filter_cache(filter.id).await?;
 
         warn!(
             "URL filter {} deleted by {}",
@@ -153,12 +300,161 @@ mod post {
         ));
         Ok(Redirect::to("/admin/url_filters").into_response())
     }
+
+    pub(super) async fn edit(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(mut state): State<AppState>,
+        Form(edit_form): Form<EditForm>,
+    ) -> Result<Response, AppError> {
+        SessionData::check_session(&session, &edit_form.authenticity_token).await?;
+
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to edit a url_filter");
+            return Err(AppError::Unauthorized);
+        };
+
+        let edit_page = format!("/admin/url_filters/edit?id={}", edit_form.id);
+        let Ok(kind) = FilterKind::try_from_value(&edit_form.kind) else {
+            debug!(
+                "Bad filter kind \"{}\" received from {}",
+                edit_form.kind, user.0.username
+            );
+            messages.error(format!("Unknown URL filter kind {}", edit_form.kind));
+            return Ok(Redirect::to(&edit_page).into_response());
+        };
+
+        let filter = match urlfilter::normalize(kind, &edit_form.filter) {
+            Err(e) => {
+                debug!(
+                    "Bad filter \"{}\" received from {} ({e})",
+                    edit_form.filter, user.0.username
+                );
+                messages.error(format!("Malformed URL filter {}: {e}", edit_form.filter));
+                return Ok(Redirect::to(&edit_page).into_response());
+            }
+            Ok(filter) => filter,
+        };
+
+        if let Some(Err(e)) = edit_form.duration.as_deref().map(validate_duration) {
+            debug!(
+                "Bad filter duration \"{:?}\" received from {} ({e})",
+                edit_form.duration, user.0.username
+            );
+            messages.error(format!("Invalid duration: {e}"));
+            return Ok(Redirect::to(&edit_page).into_response());
+        }
+
+        let url_filter = Query::find_url_filter(&state.db, edit_form.id)
+            .await?
+            .ok_or_else(|| AppError::NotFound)?;
+
+        // An empty duration leaves the expiry as it was
+        let expires_at = if edit_form.permanent.is_some() {
+            None
+        } else {
+            edit_form
+                .duration
+                .as_deref()
+                .filter(|d| !d.trim().is_empty())
+                .and_then(|d| parse_duration(d).ok())
+                .map_or(url_filter.expires_at, |d| {
+                    OffsetDateTime::now_utc().checked_add(d)
+                })
+        };
+        if let Err(e) = Mutation::update_url_filter(
+            &state.db,
+            url_filter.id,
+            filter.clone(),
+            kind,
+            edit_form.reason,
+            expires_at,
+            &user.0,
+        )
+        .await
+        {
+            if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) {
+                messages.error(format!(
+                    "There is already a URL filter {filter} ({})",
+                    urlfilter::describe(kind)
+                ));
+                return Ok(Redirect::to(&edit_page).into_response());
+            }
+            return Err(e.into());
+        }
+
+        // The filter may match less than it did, so flush everything rather than just what it
+        // matches now
+        state.urlcache.sync_filter_cache().await?;
+
+        warn!(
+            "URL filter #{} edited by {}: {} ({}, expires: {expires_at:?})",
+            url_filter.id,
+            user.0.username,
+            filter,
+            kind.to_value()
+        );
+        messages.success(format!("Edited URL filter #{} successfully", url_filter.id));
+        Ok(Redirect::to(&edit_page).into_response())
+    }
+
+    pub(super) async fn matches(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+        Form(bulk_form): Form<BulkForm>,
+    ) -> Result<Response, AppError> {
+        SessionData::check_session(&session, &bulk_form.authenticity_token).await?;
+
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to act on URL filter matches");
+            return Err(AppError::Unauthorized);
+        };
+
+        let url_filter = Query::find_url_filter(&state.db, bulk_form.id)
+            .await?
+            .ok_or_else(|| AppError::NotFound)?;
+
+        // Matches are found again, so nothing added since the preview is missed
+        let ids: Vec<i64> = matching_urls(&state, &url_filter)
+            .await?
+            .into_iter()
+            .map(|url| url.id)
+            .collect();
+
+        let affected = match bulk_form.action {
+            BulkAction::Disable => Mutation::disable_urls(&state.db, &ids).await?.rows_affected,
+            BulkAction::Delete => Mutation::delete_urls(&state.db, &ids).await?.rows_affected,
+        };
+
+        warn!(
+            "{affected} URL's matching filter {} ({:?}) by {}",
+            url_filter.id, bulk_form.action, user.0.username
+        );
+        messages.success(match bulk_form.action {
+            BulkAction::Disable => format!(
+                "Disabled {affected} URL's matching filter #{}",
+                url_filter.id
+            ),
+            BulkAction::Delete => format!(
+                "Deleted {affected} URL's matching filter #{}",
+                url_filter.id
+            ),
+        });
+        Ok(
+            Redirect::to(&format!("/admin/url_filters/matches?id={}", url_filter.id))
+                .into_response(),
+        )
+    }
 }
 
 mod get {
     use super::{
-        debug, warn, AppError, AppState, AuthSession, IntoResponse, Messages, Query, Redirect,
-        Response, Session, SessionData, State, UrlFiltersTemplate,
+        debug, matching_urls, warn, AppError, AppState, AuthSession, EditQuery, IntoResponse,
+        MatchesQuery, Messages, Query, QueryParams, Redirect, Response, Session, SessionData,
+        State, UrlFilterEditTemplate, UrlFilterMatchesTemplate, UrlFiltersTemplate,
     };
 
     pub(super) async fn url_filters(
@@ -187,6 +483,45 @@ mod get {
         .into_response())
     }
 
+    pub(super) async fn edit(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+        QueryParams(edit_query): QueryParams<EditQuery>,
+    ) -> Result<Response, AppError> {
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to access url_filter edit");
+            return Err(AppError::Unauthorized);
+        };
+
+        let authenticity_token = SessionData::new_into_session(&session).await?;
+
+        let url_filter = Query::find_url_filter(&state.db, edit_query.id)
+            .await?
+            .ok_or_else(|| AppError::NotFound)?;
+        let creator = match url_filter.user_created_id {
+            Some(id) => Query::find_user_by_id(&state.db, id).await?,
+            None => None,
+        };
+        let history = Query::fetch_url_filter_history(&state.db, url_filter.id).await?;
+
+        debug!(
+            "URL filter #{} retrieved for editing by {}",
+            url_filter.id, user.0.username
+        );
+
+        Ok(UrlFilterEditTemplate {
+            authenticity_token: &authenticity_token,
+            messages: messages.into_iter().collect(),
+            sitename: &state.env.sitename,
+            url_filter,
+            user: creator,
+            history,
+        }
+        .into_response())
+    }
+
     #[axum::debug_handler]
     pub(super) async fn flush(
         auth_session: AuthSession,
@@ -198,9 +533,45 @@ mod get {
             return Err(AppError::Unauthorized);
         };
 
-        state.urlcache.sync_regex_cache().await?;
+        state.urlcache.sync_filter_cache().await?;
         messages.success("Flushed URL filter cache");
         debug!("User {} flushed URL filter cache", user.0.username);
         Ok(Redirect::to("/admin/url_filters").into_response())
     }
+
+    pub(super) async fn matches(
+        session: Session,
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+        QueryParams(matches_query): QueryParams<MatchesQuery>,
+    ) -> Result<Response, AppError> {
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to retrieve URL filter matches");
+            return Err(AppError::Unauthorized);
+        };
+
+        let authenticity_token = SessionData::new_into_session(&session).await?;
+
+        let url_filter = Query::find_url_filter(&state.db, matches_query.id)
+            .await?
+            .ok_or_else(|| AppError::NotFound)?;
+        let urls = matching_urls(&state, &url_filter).await?;
+
+        debug!(
+            "URL filter {} matches ({}) retrieved by {}",
+            url_filter.id,
+            urls.len(),
+            user.0.username
+        );
+
+        Ok(UrlFilterMatchesTemplate {
+            authenticity_token: &authenticity_token,
+            messages: messages.into_iter().collect(),
+            sitename: &state.env.sitename,
+            url_filter,
+            urls,
+        }
+        .into_response())
+    }
 }
@@ -14,14 +14,17 @@
 
 // URL management routes
 
+use std::str::FromStr;
+
 use askama_axum::Template;
 use axum::{
-    extract::State,
+    extract::{Query as QueryParams, State},
     response::{IntoResponse, Redirect, Response},
     routing::{get, post},
-    Form, Router,
+    Form,
 };
 use axum_messages::{Message, Messages};
+use ipnetwork::IpNetwork;
 use serde::Deserialize;
 use time::OffsetDateTime;
 use tower_sessions::Session;
@@ -30,7 +33,11 @@ use tracing::{debug, info};
 use entity::url;
 use service::{Mutation, Query};
 
-use crate::{auth::AuthSession, csrf::SessionData, err::AppError, state::AppState, util::string};
+use crate::{
+    auth::AuthSession, csrf::SessionData, err::AppError, state::AppState, util::string, web::Routes,
+};
+
+use super::render;
 
 // URL listing landing page (also deletion)
 #[derive(Template)]
@@ -39,17 +46,24 @@ struct UrlsTemplate<'a> {
     authenticity_token: &'a str,
     messages: Vec<Message>,
     sitename: &'a str,
+    // Only URL's submitted from within this network are shown, if set
+    network: Option<IpNetwork>,
     urls: Vec<url::Model>,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+struct UrlsQuery {
+    network: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 struct DeleteForm {
     authenticity_token: String,
     id: i64,
 }
 
-pub fn router() -> Router<AppState> {
-    Router::new()
+pub fn router() -> Routes {
+    Routes::new()
         .route("/admin/urls", get(self::get::urls))
         .route("/admin/urls/delete", post(self::post::delete))
 }
@@ -83,8 +97,8 @@ mod post {
 
 mod get {
     use super::{
-        debug, AppError, AppState, AuthSession, IntoResponse, Messages, Query, Response, Session,
-        SessionData, State, UrlsTemplate,
+        debug, AppError, AppState, AuthSession, FromStr, IntoResponse, IpNetwork, Messages, Query,
+        QueryParams, Redirect, Response, Session, SessionData, State, UrlsQuery, UrlsTemplate,
     };
 
     pub(super) async fn urls(
@@ -92,17 +106,36 @@ mod get {
         auth_session: AuthSession,
         messages: Messages,
         State(state): State<AppState>,
+        QueryParams(urls_query): QueryParams<UrlsQuery>,
     ) -> Result<Response, AppError> {
         if auth_session.user.is_none() {
             return Err(AppError::Unauthorized);
         }
 
+        let network = match urls_query
+            .network
+            .as_deref()
+            .map(str::trim)
+            .filter(|network| !network.is_empty())
+            .map(IpNetwork::from_str)
+        {
+            Some(Err(e)) => {
+                messages.error(format!("Invalid network: {e}"));
+                return Ok(Redirect::to("/admin/urls").into_response());
+            }
+            Some(Ok(network)) => Some(network),
+            None => None,
+        };
+
         let authenticity_token = SessionData::new_into_session(&session).await?;
 
-        let urls = Query::fetch_all_urls(&state.db).await?;
+        let urls = match network {
+            Some(network) => Query::find_urls_in_network(&state.db, network).await?,
+            None => Query::fetch_all_urls(&state.db).await?,
+        };
 
         debug!(
-            "Fetching all URLs for {}",
+            "Fetching URLs (network: {network:?}) for {}",
             auth_session.user.unwrap().0.username
         );
 
@@ -110,6 +143,7 @@ mod get {
             authenticity_token: &authenticity_token,
             messages: messages.into_iter().collect(),
             sitename: &state.env.sitename,
+            network,
             urls,
         }
         .into_response())
@@ -0,0 +1,90 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/web/admin/word_report.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// Word list evaluation report
+
+use std::path::PathBuf;
+
+use askama_axum::Template;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+};
+use axum_messages::{Message, Messages};
+use tokio::task::spawn_blocking;
+use tracing::{debug, warn};
+
+use crate::{
+    auth::AuthSession,
+    err::AppError,
+    state::AppState,
+    web::Routes,
+    wordcheck::{Report, RuleSet},
+};
+
+// Number of generated links to check; the CLI can do more if needed.
+const SAMPLES: usize = 2000;
+
+#[derive(Template)]
+#[template(path = "admin/word_report.html")]
+struct WordReportTemplate<'a> {
+    messages: Vec<Message>,
+    sitename: &'a str,
+    report: Report,
+}
+
+pub fn router() -> Routes {
+    Routes::new().route("/admin/word_report", get(self::get::word_report))
+}
+
+mod get {
+    use super::{
+        debug, spawn_blocking, warn, AppError, AppState, AuthSession, IntoResponse, Messages,
+        PathBuf, Redirect, Report, Response, RuleSet, State, WordReportTemplate, SAMPLES,
+    };
+
+    pub(super) async fn word_report(
+        auth_session: AuthSession,
+        messages: Messages,
+        State(state): State<AppState>,
+    ) -> Result<Response, AppError> {
+        let Some(user) = auth_session.user else {
+            warn!("Unauthorized attempt to access word report");
+            return Err(AppError::Unauthorized);
+        };
+
+        let Some(dir) = state.env.word_rules_dir.clone().map(PathBuf::from) else {
+            messages.error("No rules directory configured (set WORD_RULES_DIR)");
+            return Ok(Redirect::to("/admin").into_response());
+        };
+
+        let link_base = format!("https://{}/", state.env.shady_host);
+        let style = state.env.shady_style();
+        let report = spawn_blocking(move || {
+            let rule_sets = RuleSet::load_dir(&dir)?;
+            Ok::<_, AppError>(Report::evaluate(&rule_sets, SAMPLES, &link_base, style))
+        })
+        .await??;
+
+        debug!("Word report generated for {}", user.0.username);
+
+        Ok(WordReportTemplate {
+            messages: messages.into_iter().collect(),
+            sitename: &state.env.sitename,
+            report,
+        }
+        .into_response())
+    }
+}
@@ -14,32 +14,189 @@
 
 // App entrypoint stuff
 
-use std::{net::SocketAddr, sync::Arc};
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
 
-use axum::Router;
+use axum::{extract::Request, middleware, response::IntoResponse, routing::MethodRouter, Router};
 use axum_login::AuthManagerLayerBuilder;
 use axum_messages::MessagesManagerLayer;
+use once_cell::sync::Lazy;
 use sea_orm::ConnectOptions;
 use time::Duration;
-use tokio::task::JoinHandle;
-use tower::ServiceBuilder;
+use tokio::{signal, task::JoinHandle};
+use tower::{Service, ServiceBuilder};
 use tower_http::{normalize_path::NormalizePathLayer, timeout::TimeoutLayer};
 use tower_sessions::{cookie::Key, CachingSessionStore, Expiry, SessionManagerLayer};
 use tower_sessions_moka_store::MokaStore;
 use tower_sessions_redis_store::{fred::prelude::*, RedisStore};
-use tracing::info;
+use tracing::{error, info};
 
 use service::Database;
 
 use crate::{
     auth::Backend,
+    autoban::AutoBan,
     bancache::BanCache,
     env::Vars,
+    geoip::GeoIp,
+    hits::HitCounter,
+    ipstorage::IpStorage,
+    jobs,
+    shortener::ShortenerResolver,
     state::AppState,
     urlcache::UrlCache,
-    web::{admin, fallback, files, submission, url},
+    web::{admin, banscope, fallback, files, submission, url},
 };
 
+// A path claimed by a router, which takes priority over the shady catch-all route
+#[derive(Debug, Clone, Copy)]
+pub enum ReservedPath {
+    // A route, matched the way the router matches it
+    Route(&'static str),
+    // This path, and everything below it
+    Prefix(&'static str),
+}
+
+impl ReservedPath {
+    pub(crate) fn shadows(self, path: &str) -> bool {
+        match self {
+            Self::Route(route) => {
+                let mut route = route.split('/');
+                let mut path = path.split('/');
+                loop {
+                    match (route.next(), path.next()) {
+                        (None, None) => return true,
+                        // A wildcard takes the rest of the path, as long as there is some
+                        (Some(wanted), Some(segment)) if wanted.starts_with('*') => {
+                            return !segment.is_empty()
+                        }
+                        // A parameter takes any one segment
+                        (Some(wanted), Some(segment)) if wanted.starts_with(':') => {
+                            if segment.is_empty() {
+                                return false;
+                            }
+                        }
+                        (Some(wanted), Some(segment)) if wanted == segment => {}
+                        _ => return false,
+                    }
+                }
+            }
+            Self::Prefix(reserved) => path
+                .strip_prefix(reserved)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/')),
+        }
+    }
+}
+
+// A router that keeps track of the paths it claims as routes are added to it
+#[derive(Default)]
+pub struct Routes {
+    router: Router<AppState>,
+    reserved: Vec<ReservedPath>,
+}
+
+impl Routes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn route(mut self, path: &'static str, method_router: MethodRouter<AppState>) -> Self {
+        self.router = self.router.route(path, method_router);
+        self.reserved.push(ReservedPath::Route(path));
+        self
+    }
+
+    // Add a route without claiming its path, for catch-alls like the shady route
+    pub fn unclaimed_route(
+        mut self,
+        path: &'static str,
+        method_router: MethodRouter<AppState>,
+    ) -> Self {
+        self.router = self.router.route(path, method_router);
+        self
+    }
+
+    // Serve a path and everything below it
+    pub fn nest_service<T>(mut self, path: &'static str, service: T) -> Self
+    where
+        T: Service<Request, Error = Infallible> + Clone + Send + 'static,
+        T::Response: IntoResponse,
+        T::Future: Send + 'static,
+    {
+        self.router = self.router.nest_service(path, service);
+        self.reserved.push(ReservedPath::Prefix(path));
+        self
+    }
+
+    pub fn merge(mut self, other: Self) -> Self {
+        self.router = self.router.merge(other.router);
+        self.reserved.extend(other.reserved);
+        self
+    }
+
+    pub(crate) fn reserved(&self) -> &[ReservedPath] {
+        &self.reserved
+    }
+}
+
+// Every router merged ahead of the fallback.
+// Anything none of them claim ends up at the shady route.
+const ROUTERS: [fn() -> Routes; 4] = [
+    admin::router,
+    files::router,
+    submission::router,
+    url::router,
+];
+
+// Every path claimed by the routers
+static RESERVED: Lazy<Vec<ReservedPath>> = Lazy::new(|| {
+    ROUTERS
+        .iter()
+        .flat_map(|router| router().reserved)
+        .collect()
+});
+
+// Check if a shady name would be shadowed by another route, and so could never be reached
+pub fn is_reserved_path(shady: &str) -> bool {
+    // Trailing slashes are trimmed from requests, so these could never match either
+    if shady.is_empty() || shady.ends_with('/') {
+        return true;
+    }
+
+    let path = format!("/{shady}");
+    RESERVED.iter().any(|reserved| reserved.shadows(&path))
+}
+
+// Wait for Ctrl-C, or SIGTERM where there is such a thing
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = signal::ctrl_c().await {
+            error!("Could not listen for Ctrl-C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                error!("Could not listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => (),
+        () = terminate => (),
+    }
+    info!("Shutting down");
+}
+
 // This holds our app state that we need later
 pub struct App {
     state: AppState,
@@ -52,9 +209,24 @@ pub enum RuntimeError {
     #[error(transparent)]
     Env(#[from] crate::env::EnvError),
 
+    #[error(transparent)]
+    BanCache(#[from] crate::bancache::BanCacheError),
+
     #[error(transparent)]
     UrlCache(#[from] crate::urlcache::UrlCacheError),
 
+    #[error(transparent)]
+    GeoIp(#[from] crate::geoip::GeoIpError),
+
+    #[error(transparent)]
+    IpStorage(#[from] crate::ipstorage::IpStorageError),
+
+    #[error(transparent)]
+    AutoBan(#[from] crate::autoban::AutoBanError),
+
+    #[error(transparent)]
+    Shortener(#[from] crate::shortener::ShortenerError),
+
     #[error(transparent)]
     Db(#[from] sea_orm::DbErr),
 
@@ -90,13 +262,10 @@ impl App {
             .sqlx_logging(false);
         let db = Arc::new(Database::get_with_connect_options(opt).await?);
 
-        // TODO: configurable
-        let bancache = BanCache::new(
-            db.clone(),
-            env.ban_cache_max_entries,
-            env.ban_cache_ttl,
-            env.ban_cache_idle,
-        );
+        let ip_storage = IpStorage::new(&env)?;
+        let geoip = GeoIp::new(&env).await?;
+        let bancache = BanCache::new(db.clone(), geoip.clone()).await?;
+        let autoban = AutoBan::new(db.clone(), bancache.clone(), &env).await?;
         let urlcache = UrlCache::new(
             db.clone(),
             env.url_cache_max_entries,
@@ -104,13 +273,21 @@ impl App {
             env.url_cache_idle,
         )
         .await?;
+        let shortener =
+            ShortenerResolver::new(env.shortener_timeout, env.shortener_resolver.as_deref())?;
 
         Ok(Self {
             state: AppState {
                 db: db.clone(),
+                url_policy: Arc::new(env.url_policy()),
                 env,
                 bancache,
+                geoip,
+                autoban,
                 urlcache,
+                shortener,
+                hits: HitCounter::default(),
+                ip_storage,
             },
             redis_pool,
             redis_conn,
@@ -119,6 +296,7 @@ impl App {
 
     // Begin serving
     pub(crate) async fn serve(self) -> Result<(), RuntimeError> {
+        let redis_pool = self.redis_pool.clone();
         let redis_store = RedisStore::new(self.redis_pool);
         let moka_store = MokaStore::new(Some(100));
         let caching_store = CachingSessionStore::new(moka_store, redis_store);
@@ -137,13 +315,19 @@ impl App {
             .layer(self.state.env.ip_source.clone().into_extension());
 
         let bind = self.state.env.bind.clone();
+        // Kept for after we stop serving
+        let (hits, db) = (self.state.hits.clone(), self.state.db.clone());
 
-        let app = Router::new()
-            .merge(admin::router())
-            .merge(files::router())
-            .merge(submission::router())
-            .merge(url::router())
+        jobs::spawn_all(&self.state);
+
+        let app = ROUTERS
+            .iter()
+            .fold(Router::new(), |app, router| app.merge(router().router))
             .merge(fallback::router())
+            .layer(middleware::from_fn_with_state(
+                self.state.clone(),
+                banscope::enforce,
+            ))
             .layer(services)
             .with_state(self.state);
 
@@ -153,8 +337,15 @@ impl App {
             listener,
             app.into_make_service_with_connect_info::<SocketAddr>(),
         )
+        .with_graceful_shutdown(shutdown_signal())
         .await?;
 
+        // Don't lose the hits counted since the last flush
+        if let Err(e) = hits.flush(&db).await {
+            error!("Could not write out hit counts: {e}");
+        }
+
+        redis_pool.quit().await?;
         self.redis_conn.await??;
 
         info!("Server terminating");
@@ -0,0 +1,112 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/web/banscope.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// Ban scopes, and the middleware enforcing them.
+//
+// Every request is sorted into the scope it falls under by its path, using the same reserved
+// paths the routers claim. Site-wide bans block everything, including paths no other scope covers.
+// Someone already logged in isn't held to site-wide or admin bans, so admins can't lock themselves
+// out by banning their own network.
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use axum_client_ip::SecureClientIp;
+use once_cell::sync::Lazy;
+use tracing::info;
+
+use entity::sea_orm_active_enums::BanScope;
+
+use crate::{
+    auth::AuthSession,
+    err::AppError,
+    state::AppState,
+    web::{admin, is_reserved_path, url, ReservedPath},
+};
+
+// Paths under the admin and URL routers, which have scopes of their own
+static ADMIN_PATHS: Lazy<Vec<ReservedPath>> = Lazy::new(|| admin::router().reserved().to_vec());
+static URL_PATHS: Lazy<Vec<ReservedPath>> = Lazy::new(|| url::router().reserved().to_vec());
+
+// Every scope, in the order we show them
+pub const SCOPES: [BanScope; 4] = [
+    BanScope::Submit,
+    BanScope::Redirect,
+    BanScope::Site,
+    BanScope::Admin,
+];
+
+// Human-readable name of a ban scope
+pub const fn describe(scope: BanScope) -> &'static str {
+    match scope {
+        BanScope::Submit => "Submitting links",
+        BanScope::Redirect => "Following links",
+        BanScope::Site => "Whole site",
+        BanScope::Admin => "Admin login",
+    }
+}
+
+// The scope a request falls under, if any besides the whole site
+fn request_scope(path: &str) -> Option<BanScope> {
+    if path == "/submit" {
+        Some(BanScope::Submit)
+    } else if ADMIN_PATHS.iter().any(|reserved| reserved.shadows(path)) {
+        Some(BanScope::Admin)
+    } else if URL_PATHS.iter().any(|reserved| reserved.shadows(path))
+        || !is_reserved_path(path.strip_prefix('/').unwrap_or(path))
+    {
+        // Anything nobody else claims is a shady link
+        Some(BanScope::Redirect)
+    } else {
+        None
+    }
+}
+
+// Reject requests from IP's with a ban covering them
+pub async fn enforce(
+    SecureClientIp(addr): SecureClientIp,
+    auth_session: AuthSession,
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let scope = request_scope(request.uri().path());
+    let logged_in = auth_session.user.is_some();
+    let applies = |bans: &[(i64, BanScope)]| -> Vec<i64> {
+        bans.iter()
+            .filter(|(_, ban_scope)| *ban_scope == BanScope::Site || Some(*ban_scope) == scope)
+            .filter(|(_, ban_scope)| {
+                !(logged_in && matches!(ban_scope, BanScope::Site | BanScope::Admin))
+            })
+            .map(|(id, _)| *id)
+            .collect()
+    };
+    let found = state.bancache.check_ban(addr).await?;
+    let bans = applies(&found.cidr);
+    let geo_bans = applies(&found.geo);
+
+    if !bans.is_empty() || !geo_bans.is_empty() {
+        state.hits.cidr_ban_hits(&bans);
+        state.hits.geo_ban_hits(&geo_bans);
+        info!(
+            "Banned client ({addr}) attempted to access {} (bans {bans:?}, GeoIP bans {geo_bans:?})",
+            request.uri().path()
+        );
+        return Err(AppError::Unauthorized);
+    }
+
+    Ok(next.run(request).await)
+}
@@ -15,13 +15,12 @@
 // Service static files as a fallback.
 // Ideally, the web server should do this for us, but this is useful for development mode.
 
-use axum::Router;
 use tower_http::services::{ServeDir, ServeFile};
 
-use crate::state::AppState;
+use crate::web::Routes;
 
-pub fn router() -> Router<AppState> {
-    Router::new()
+pub fn router() -> Routes {
+    Routes::new()
         .nest_service("/robots.txt", ServeFile::new("static/robots.txt"))
         .nest_service("/ads.txt", ServeFile::new("static/ads.txt"))
         .nest_service("/app-ads.txt", ServeFile::new("static/app-ads.txt"))
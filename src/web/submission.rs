@@ -14,23 +14,33 @@
 
 // URL submission routes
 
+use std::net::IpAddr;
+
 use askama_axum::Template;
 use axum::{
     extract::State,
     response::{IntoResponse, Response},
     routing::{get, post},
-    Form, Router,
+    Form,
 };
 use axum_client_ip::SecureClientIp;
 use axum_messages::{Message, Messages};
 
 use serde::Deserialize;
-use tracing::{debug, info};
-use validator::Validate;
+use tracing::{debug, error, info};
+use url::Url;
+use validator::{Validate, ValidateArgs};
 
 use service::Mutation;
 
-use crate::{err::AppError, generate::Generator, state::AppState, validators::validate_url};
+use crate::{
+    err::AppError,
+    generate::Generator,
+    shortener::ShortenerError,
+    state::AppState,
+    validators::{validate_url, ShortenerPolicy, UrlPolicy},
+    web::Routes,
+};
 
 // Home page
 #[derive(Template)]
@@ -52,13 +62,14 @@ struct SubmissionTemplate<'a> {
 }
 
 #[derive(Debug, Clone, Validate, Deserialize)]
+#[validate(context = UrlPolicy)]
 struct UrlForm {
-    #[validate(length(min = 3), custom(function = validate_url))]
+    #[validate(length(min = 3), custom(function = validate_url, use_context))]
     pub(super) url: String,
 }
 
-pub fn router() -> Router<AppState> {
-    Router::new()
+pub fn router() -> Routes {
+    Routes::new()
         .route("/", get(self::get::index))
         .route("/submit", post(self::post::submit))
 }
@@ -81,10 +92,20 @@ mod get {
 
 mod post {
     use super::{
-        debug, info, AppError, AppState, Form, Generator, IntoResponse, Messages, Mutation,
-        Response, SecureClientIp, State, SubmissionTemplate, UrlForm, Validate,
+        debug, error, info, AppError, AppState, Form, Generator, IntoResponse, IpAddr, Messages,
+        Mutation, Response, SecureClientIp, ShortenerError, ShortenerPolicy, State,
+        SubmissionTemplate, Url, UrlForm, ValidateArgs,
     };
 
+    // Reject a submission, counting it towards an automatic ban.
+    // Only what the client sent counts; failing to reach a shortener isn't their fault.
+    async fn reject(state: &AppState, addr: IpAddr, url: String, error_reason: String) -> AppError {
+        if let Err(e) = state.autoban.strike(addr, &error_reason).await {
+            error!("Could not record strike against {addr}: {e}");
+        }
+        AppError::UrlValidation(url, error_reason)
+    }
+
     #[axum::debug_handler]
     pub(super) async fn submit(
         messages: Messages,
@@ -92,42 +113,72 @@ mod post {
         State(state): State<AppState>,
         Form(url_form): Form<UrlForm>,
     ) -> Result<Response, AppError> {
-        // Check the ban cache (which will also check the db for us)
-        if state.bancache.check_ban(addr).await? {
-            info!(
-                "Banned client ({addr}) attempted to submit url: {}",
-                url_form.url
-            );
-            return Err(AppError::Unauthorized);
-        }
-
-        if let Err(e) = url_form.validate() {
+        // Bans are enforced by the banscope middleware before we get here
+        if let Err(e) = url_form.validate_with_args(&state.url_policy) {
             // Failed the validation checks
             let error_reason = e
                 .field_errors()
                 .get("url")
                 .map_or("Unknown error".to_string(), |v| v[0].to_string());
             debug!("Invalid URL submitted ({}): {error_reason}", url_form.url);
-            return Err(AppError::UrlValidation(url_form.url, error_reason));
+            return Err(reject(&state, addr, url_form.url, error_reason).await);
         }
 
-        if state.urlcache.check_url_banned(&url_form.url).await? {
-            info!("Blacklisted URL submitted: {}", url_form.url);
-
-            return Err(AppError::UrlValidation(
-                url_form.url,
-                "URL is banned".to_string(),
-            ));
+        // Shadify where shortened links go, rather than the shortener
+        let url = if state.url_policy.shortener_policy == ShortenerPolicy::Expand
+            && Url::parse(&url_form.url).is_ok_and(|u| state.url_policy.is_shortener(&u))
+        {
+            match state
+                .shortener
+                .expand(&url_form.url, &state.url_policy)
+                .await
+            {
+                Ok(url) => url,
+                Err(e @ ShortenerError::Invalid(_)) => {
+                    info!("Shortened URL {} goes somewhere invalid: {e}", url_form.url);
+                    return Err(reject(&state, addr, url_form.url.clone(), e.to_string()).await);
+                }
+                Err(e) => {
+                    info!("Could not expand shortened URL {}: {e}", url_form.url);
+                    return Err(AppError::UrlValidation(url_form.url.clone(), e.to_string()));
+                }
+            }
+        } else {
+            url_form.url.clone()
+        };
+
+        for check_url in [&url_form.url, &url] {
+            let matched = state.urlcache.check_url_banned(check_url).await?;
+            if !matched.is_empty() {
+                state.hits.url_filter_hits(&matched);
+                info!("Blacklisted URL submitted: {check_url} (filters {matched:?})");
+
+                return Err(reject(
+                    &state,
+                    addr,
+                    url_form.url.clone(),
+                    "URL is banned".to_string(),
+                )
+                .await);
+            }
         }
 
-        let shady = Generator::shady_filename().await;
-        Mutation::create_url(&state.db, &url_form.url, &shady, Some(addr.to_string())).await?;
+        let shady = Generator::shady_name(state.env.shady_style()).await;
+        let stored_ip = state.ip_storage.store(addr);
+        Mutation::create_url(
+            &state.db,
+            &url,
+            &shady.path,
+            stored_ip.ip,
+            stored_ip.ip_hmac,
+        )
+        .await?;
 
-        debug!("URL created: {} -> {shady}", url_form.url);
+        debug!("URL created: {url} -> {}", shady.path);
 
         Ok(SubmissionTemplate {
-            url: &url_form.url,
-            shady: &shady,
+            url: &url,
+            shady: &shady.to_string(),
             messages: messages.into_iter().collect(),
             shady_host: &state.env.shady_host,
         }
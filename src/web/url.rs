@@ -18,19 +18,19 @@ use axum::{
     extract::{Path, State},
     response::{IntoResponse, Redirect, Response},
     routing::get,
-    Router,
 };
 use itertools::join;
 use tracing::trace;
 
 use service::Query;
 
-use crate::{err::AppError, state::AppState};
+use crate::{err::AppError, state::AppState, web::Routes};
 
-pub fn router() -> Router<AppState> {
-    Router::new()
+pub fn router() -> Routes {
+    Routes::new()
         .route("/reverse-map/*url", get(self::get::url))
-        .route("/*shady", get(self::get::shady))
+        // The shady route itself is a catch-all, and doesn't reserve anything
+        .unclaimed_route("/*shady", get(self::get::shady))
 }
 
 mod get {
@@ -57,17 +57,25 @@ mod get {
         Path(shady): Path<String>,
         State(state): State<AppState>,
     ) -> Result<Response, AppError> {
-        Query::find_url_by_shady_string(&state.db, &shady)
-            .await?
-            .map_or_else(
-                || {
-                    trace!("Couldn't find URL {shady}");
-                    Err(AppError::NotFound)
-                },
-                |url| {
-                    trace!("Found URL {shady} => {}", url.url);
-                    Ok(Redirect::to(&url.url).into_response())
-                },
-            )
+        let Some(url) = Query::find_url_by_shady_string(&state.db, &shady).await? else {
+            trace!("Couldn't find URL {shady}");
+            return Err(AppError::NotFound);
+        };
+
+        if url.disabled {
+            trace!("URL {shady} is disabled");
+            return Err(AppError::NotFound);
+        }
+
+        // Filters added after the link was made still apply
+        let matched = state.urlcache.check_url_banned(&url.url).await?;
+        if !matched.is_empty() {
+            trace!("URL {shady} => {} matches filters {matched:?}", url.url);
+            state.hits.url_filter_hits(&matched);
+            return Err(AppError::NotFound);
+        }
+
+        trace!("Found URL {shady} => {}", url.url);
+        Ok(Redirect::to(&url.url).into_response())
     }
 }
@@ -0,0 +1,240 @@
+/* SPDX-License-Identifier: CC0-1.0
+ *
+ * src/wordcheck.rs
+ *
+ * This file is a component of ShadyURL by Elizabeth Myers.
+ *
+ * To the extent possible under law, the person who associated CC0 with
+ * ShadyURL has waived all copyright and related or neighboring rights
+ * to ShadyURL.
+ *
+ * You should have received a copy of the CC0 legalcode along with this
+ * work.  If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+ */
+
+// See what dirty words work and don't work.
+//
+// This checks the generator word lists, and a sample of generated links, against blocking rules
+// exported from chat platforms, mail filters, and so on. Rules live in a directory, one rule set
+// per file:
+//     - Files ending in .regex contain one regex per line.
+//     - Any other file contains keywords, one per line (or separated by commas). Keywords are
+//       case-insensitive and match whole words, unless they begin or end with a *, which allows
+//       them to match inside of a word (as in Discord's AutoMod). A keyword that's nothing but
+//       *'s would match everything, so it's ignored.
+// Blank lines and lines beginning with # are ignored.
+
+use std::{
+    collections::BTreeMap,
+    fs::{read_dir, read_to_string},
+    path::Path,
+};
+
+use rand::prelude::*;
+use regex::Regex;
+
+use crate::generate::{strings, Generator, Style};
+
+// Lists checked, and what we call them in reports
+const WORD_LISTS: [(&str, &[&str]); 5] = [
+    ("nsfw", &strings::NSFW),
+    ("dirs", &strings::DIRS),
+    ("query_keys", &strings::QUERY_KEYS),
+    ("ext", &strings::EXT),
+    ("ext_exe", &strings::EXT_EXE),
+];
+
+// How many example blocked links to keep per rule set
+const MAX_EXAMPLES: usize = 10;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WordCheckError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("Bad regex in {}, line {}: {}", .0, .1, .2)]
+    Regex(String, usize, regex::Error),
+}
+
+// A single blocking rule, as written in its file, and compiled.
+pub struct Rule {
+    pub(crate) pattern: String,
+    regex: Regex,
+}
+
+pub struct RuleSet {
+    pub(crate) name: String,
+    pub(crate) rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    // Load a rule set from a file
+    pub(crate) fn load(path: &Path) -> Result<Self, WordCheckError> {
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |n| n.to_string_lossy().to_string(),
+        );
+        let is_regex = path.extension().is_some_and(|ext| ext == "regex");
+
+        let mut rules = Vec::new();
+        for (lineno, line) in read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if is_regex {
+                let regex = Regex::new(line)
+                    .map_err(|e| WordCheckError::Regex(name.clone(), lineno + 1, e))?;
+                rules.push(Rule {
+                    pattern: line.to_string(),
+                    regex,
+                });
+                continue;
+            }
+
+            for keyword in line.split(',').map(str::trim).filter(|k| !k.is_empty()) {
+                let core = keyword.trim_matches('*');
+                if core.trim().is_empty() {
+                    continue;
+                }
+                let start = if keyword.starts_with('*') { "" } else { r"\b" };
+                let end = if keyword.ends_with('*') { "" } else { r"\b" };
+                let regex = Regex::new(&format!("(?i){start}{}{end}", regex::escape(core)))
+                    .map_err(|e| WordCheckError::Regex(name.clone(), lineno + 1, e))?;
+                rules.push(Rule {
+                    pattern: keyword.to_string(),
+                    regex,
+                });
+            }
+        }
+
+        Ok(Self { name, rules })
+    }
+
+    // Load every rule set in a directory, sorted by name
+    pub(crate) fn load_dir(dir: &Path) -> Result<Vec<Self>, WordCheckError> {
+        let mut rule_sets = Vec::new();
+        for entry in read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() {
+                rule_sets.push(Self::load(&path)?);
+            }
+        }
+
+        rule_sets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(rule_sets)
+    }
+
+    // Find the first rule that blocks the given text
+    pub(crate) fn check(&self, text: &str) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.regex.is_match(text))
+    }
+}
+
+// Fragments that are the same, or look the same once case and separators are ignored.
+// Each entry is the list name, followed by the fragment.
+pub struct Duplicate {
+    pub(crate) entries: Vec<(&'static str, &'static str)>,
+}
+
+impl Duplicate {
+    // True if this is the exact same fragment repeated
+    pub(crate) fn is_exact(&self) -> bool {
+        self.entries.windows(2).all(|w| w[0] == w[1])
+    }
+}
+
+pub struct RuleSetReport {
+    pub(crate) name: String,
+    pub(crate) rule_count: usize,
+    // List name, fragment, and the pattern of the rule that blocked it
+    pub(crate) blocked_fragments: Vec<(&'static str, &'static str, String)>,
+    pub(crate) links_blocked: usize,
+    // Blocked link, and the pattern of the rule that blocked it
+    pub(crate) examples: Vec<(String, String)>,
+}
+
+pub struct Report {
+    pub(crate) fragment_count: usize,
+    pub(crate) links_sampled: usize,
+    pub(crate) duplicates: Vec<Duplicate>,
+    pub(crate) rule_sets: Vec<RuleSetReport>,
+}
+
+impl Report {
+    // Find duplicated fragments in the word lists
+    fn find_duplicates() -> Vec<Duplicate> {
+        let mut seen: BTreeMap<String, Vec<(&'static str, &'static str)>> = BTreeMap::new();
+        for (list, words) in WORD_LISTS {
+            for word in words {
+                let key: String = word
+                    .chars()
+                    .filter(char::is_ascii_alphanumeric)
+                    .map(|ch| ch.to_ascii_lowercase())
+                    .collect();
+                seen.entry(key).or_default().push((list, word));
+            }
+        }
+
+        seen.into_values()
+            .filter(|entries| entries.len() > 1)
+            .map(|entries| Duplicate { entries })
+            .collect()
+    }
+
+    // Check the word lists, and links made from the given number of generated names, against
+    // all the rule sets given. Links are prefixed with link_base, as they would be when shared.
+    pub(crate) fn evaluate(
+        rule_sets: &[RuleSet],
+        samples: usize,
+        link_base: &str,
+        style: Style,
+    ) -> Self {
+        let mut generator = Generator::new(thread_rng(), style);
+        let links: Vec<String> = (0..samples)
+            .map(|_| format!("{link_base}{}", generator.generate_shady_name()))
+            .collect();
+
+        let rule_sets = rule_sets
+            .iter()
+            .map(|rule_set| {
+                let blocked_fragments = WORD_LISTS
+                    .iter()
+                    .flat_map(|(list, words)| words.iter().map(move |word| (*list, *word)))
+                    .filter_map(|(list, word)| {
+                        rule_set
+                            .check(word)
+                            .map(|rule| (list, word, rule.pattern.clone()))
+                    })
+                    .collect();
+
+                let mut links_blocked = 0;
+                let mut examples = Vec::new();
+                for link in &links {
+                    if let Some(rule) = rule_set.check(link) {
+                        links_blocked += 1;
+                        if examples.len() < MAX_EXAMPLES {
+                            examples.push((link.clone(), rule.pattern.clone()));
+                        }
+                    }
+                }
+
+                RuleSetReport {
+                    name: rule_set.name.clone(),
+                    rule_count: rule_set.rules.len(),
+                    blocked_fragments,
+                    links_blocked,
+                    examples,
+                }
+            })
+            .collect();
+
+        Self {
+            fragment_count: WORD_LISTS.iter().map(|(_, words)| words.len()).sum(),
+            links_sampled: samples,
+            duplicates: Self::find_duplicates(),
+            rule_sets,
+        }
+    }
+}